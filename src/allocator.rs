@@ -0,0 +1,123 @@
+use std::ops::Range;
+
+use crate::world::EntityId;
+
+///
+/// Decides which id a newly created entity gets. Allocators are told when
+/// an entity is deleted, so they can hand its id out again if they want to.
+/// Ids that are still in use are skipped, so an allocator must eventually
+/// return one that isn't.
+/// ```
+/// use enco::allocator::*;
+/// use enco::world::*;
+///
+/// let mut world = World::with_allocator(RecyclingAllocator::new());
+/// let entity_id = world.create_entity().done();
+/// world.delete_entity(&entity_id).unwrap();
+///
+/// assert_eq!(world.create_entity().done(), entity_id);
+/// ```
+pub trait IdAllocator: CloneAllocator {
+    fn allocate(&mut self) -> EntityId;
+
+    fn free(&mut self, _entity_id: EntityId) {}
+
+    ///
+    /// Called when the world stops using every id from `next_id` on, after
+    /// renumbering or truncating its entities, so that the allocator can
+    /// start over from there
+    fn reset(&mut self, _next_id: EntityId) {}
+
+    ///
+    /// Sets aside `count` consecutive ids that `allocate` will never return,
+    /// for `World::reserve_ids`. Allocators that can't promise that return
+    /// `None`, which is the default.
+    fn reserve(&mut self, _count: usize) -> Option<Range<EntityId>> {
+        None
+    }
+}
+
+///
+/// Lets a boxed allocator be cloned. Implemented automatically for every
+/// allocator that is `Clone`.
+pub trait CloneAllocator {
+    fn clone_allocator(&self) -> Box<dyn IdAllocator>;
+}
+
+impl<T: IdAllocator + Clone + 'static> CloneAllocator for T {
+    fn clone_allocator(&self) -> Box<dyn IdAllocator> {
+        Box::new(self.clone())
+    }
+}
+
+///
+/// Hands out strictly increasing ids, never reusing one. This is the
+/// default allocator.
+#[derive(Clone, Default)]
+pub struct MonotonicAllocator {
+    next_id: EntityId,
+}
+
+impl MonotonicAllocator {
+    pub fn new() -> Self {
+        MonotonicAllocator { next_id: 0 }
+    }
+}
+
+impl IdAllocator for MonotonicAllocator {
+    fn allocate(&mut self) -> EntityId {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    fn reset(&mut self, next_id: EntityId) {
+        self.next_id = self.next_id.min(next_id);
+    }
+
+    fn reserve(&mut self, count: usize) -> Option<Range<EntityId>> {
+        self.next_id += count;
+        Some(self.next_id - count..self.next_id)
+    }
+}
+
+///
+/// Reuses the ids of deleted entities, most recently deleted first, before
+/// handing out new ones
+#[derive(Clone, Default)]
+pub struct RecyclingAllocator {
+    next_id: EntityId,
+    free_ids: Vec<EntityId>,
+}
+
+impl RecyclingAllocator {
+    pub fn new() -> Self {
+        RecyclingAllocator {
+            next_id: 0,
+            free_ids: Vec::new(),
+        }
+    }
+}
+
+impl IdAllocator for RecyclingAllocator {
+    fn allocate(&mut self) -> EntityId {
+        if let Some(entity_id) = self.free_ids.pop() {
+            return entity_id;
+        }
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    fn free(&mut self, entity_id: EntityId) {
+        self.free_ids.push(entity_id);
+    }
+
+    fn reset(&mut self, next_id: EntityId) {
+        self.next_id = self.next_id.min(next_id);
+        self.free_ids.retain(|entity_id| *entity_id < next_id);
+    }
+
+    fn reserve(&mut self, count: usize) -> Option<Range<EntityId>> {
+        self.next_id += count;
+        Some(self.next_id - count..self.next_id)
+    }
+}
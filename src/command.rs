@@ -0,0 +1,119 @@
+use std::any::{type_name, Any};
+
+use crate::custom_errors::*;
+use crate::world::*;
+
+type Command = Box<dyn FnOnce(&mut World) -> Result<(), WorldError>>;
+
+///
+/// Records structural changes to be applied to a world later on, for when
+/// the world is borrowed while deciding what to change
+/// ```
+/// use enco::command::*;
+/// use enco::world::*;
+/// struct Health(i32);
+/// struct Dead;
+///
+/// let mut world = World::new();
+/// let entity_id = world.create_entity().with(Health(0)).done();
+///
+/// let mut commands = CommandBuffer::new();
+/// for (entity_id, health) in world.query_optional::<Health>() {
+///     if health.is_some_and(|health| health.0 <= 0) {
+///         commands.add(entity_id, Dead);
+///     }
+/// }
+/// commands.apply(&mut world).unwrap();
+///
+/// assert!(world.has_component::<Dead>(&entity_id));
+/// ```
+#[derive(Default)]
+pub struct CommandBuffer {
+    commands: Vec<Command>,
+}
+
+impl CommandBuffer {
+    pub fn new() -> Self {
+        CommandBuffer {
+            commands: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    ///
+    /// Adds a component to an entity. Conflicts if the entity already has a
+    /// component of that type, including one added earlier in the buffer.
+    pub fn add<T: Any>(&mut self, entity_id: EntityId, component: T) {
+        self.commands.push(Box::new(move |world: &mut World| {
+            world.add_component(&entity_id, component).context(format!(
+                "adding `{}` to entity {entity_id}",
+                type_name::<T>()
+            ))
+        }));
+    }
+
+    ///
+    /// Deletes a component from an entity. Conflicts if the entity doesn't
+    /// have it by the time the command is applied.
+    pub fn remove<T: Any>(&mut self, entity_id: EntityId) {
+        self.commands.push(Box::new(move |world: &mut World| {
+            world
+                .num_components(&entity_id)
+                .and_then(|_| world.delete_component::<T>(&entity_id))
+                .context(format!(
+                    "removing `{}` from entity {entity_id}",
+                    type_name::<T>()
+                ))
+        }));
+    }
+
+    ///
+    /// Deletes an entity. Conflicts if the entity is already gone.
+    pub fn despawn(&mut self, entity_id: EntityId) {
+        self.commands.push(Box::new(move |world: &mut World| {
+            world
+                .delete_entity(&entity_id)
+                .context(format!("despawning entity {entity_id}"))
+        }));
+    }
+
+    ///
+    /// Applies the commands in the order they were recorded. A command that
+    /// conflicts with the world, or with a command before it, is skipped
+    /// and its error reported; the others are applied regardless.
+    /// ```
+    /// use enco::command::*;
+    /// use enco::world::*;
+    /// struct Health(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().done();
+    ///
+    /// let mut commands = CommandBuffer::new();
+    /// commands.despawn(entity_id);
+    /// commands.add(entity_id, Health(10));
+    ///
+    /// let errors = commands.apply(&mut world).unwrap_err();
+    /// assert_eq!(errors.len(), 1);
+    /// assert_eq!(world.num_entities(), 0);
+    /// ```
+    pub fn apply(self, world: &mut World) -> Result<(), Vec<WorldError>> {
+        let errors: Vec<WorldError> = self
+            .commands
+            .into_iter()
+            .filter_map(|command| command(world).err())
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::{any::Any, marker::PhantomData};
+
+///
+/// Metadata about a component type. Any `'static` type can be stored as a
+/// component; implementing this trait and registering the type with
+/// `World::register_component` gives it a stable name that doesn't depend
+/// on the compiler's `TypeId`, for use in serialization and debugging.
+/// ```
+/// use enco::component::*;
+/// use enco::world::*;
+///
+/// struct Position(i32, i32);
+///
+/// impl Component for Position {
+///     fn name() -> &'static str {
+///         "Position"
+///     }
+/// }
+///
+/// let mut world = World::new();
+/// world.register_component::<Position>();
+/// assert_eq!(world.component_name::<Position>(), Some("Position"));
+/// ```
+pub trait Component: Any {
+    ///
+    /// The stable name of the component type. Defaults to the type's path,
+    /// which may change between compiler versions.
+    fn name() -> &'static str
+    where
+        Self: Sized,
+    {
+        std::any::type_name::<Self>()
+    }
+}
+
+///
+/// Does nothing, but only compiles if `T` can be stored as a component.
+/// Components live in `Box<dyn Any>`, so they must be `'static`: types that
+/// borrow data, like `&str` or structs holding a reference, are rejected.
+/// Calling this next to the definition of a component type gives a clearer
+/// error than the first `with` or `add_component` that uses it.
+/// ```
+/// use enco::component::*;
+/// struct Name(String);
+///
+/// assert_component_type::<Name>();
+/// ```
+///
+/// Borrowed types are rejected at compile time:
+/// ```compile_fail
+/// use enco::component::*;
+/// struct Name<'a>(&'a str);
+///
+/// fn check<'a>() {
+///     assert_component_type::<Name<'a>>();
+/// }
+/// ```
+/// ```compile_fail
+/// use enco::world::*;
+/// struct Name<'a>(&'a str);
+///
+/// fn spawn(world: &mut World, name: &str) {
+///     world.create_entity().with(Name(name)).done();
+/// }
+/// ```
+pub fn assert_component_type<T: Any>() {}
+
+///
+/// A zero-sized handle naming a component type, so that the type only has
+/// to be spelled out once and the key passed to `World::get`, `World::set`
+/// and `World::remove` instead of a turbofish
+/// ```
+/// use enco::component::*;
+/// use enco::world::*;
+/// struct Health(i32);
+///
+/// const HEALTH: ComponentKey<Health> = ComponentKey::new();
+///
+/// let mut world = World::new();
+/// let entity_id = world.create_entity().with(Health(10)).done();
+///
+/// assert_eq!(world.get(&entity_id, HEALTH).unwrap().0, 10);
+/// ```
+pub struct ComponentKey<T: Any>(PhantomData<fn() -> T>);
+
+impl<T: Any> ComponentKey<T> {
+    pub const fn new() -> Self {
+        ComponentKey(PhantomData)
+    }
+}
+
+impl<T: Any> Default for ComponentKey<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Any> Clone for ComponentKey<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: Any> Copy for ComponentKey<T> {}
@@ -1,4 +1,6 @@
-use std::any::TypeId;
+use std::{any::TypeId, error::Error, fmt};
+
+use crate::world::{EntityId, ScopeId};
 
 #[derive(Debug)]
 pub enum WorldError {
@@ -7,4 +9,79 @@ pub enum WorldError {
     EntityAlreadyHasComponent(TypeId),
     CreateEntity,
     DeleteEntity,
+    ScopeDoesNotExist(ScopeId),
+    ResourceDoesNotExist(TypeId),
+    NoMatchingEntity(TypeId),
+    MultipleMatchingEntities(TypeId),
+    ParentCycle(EntityId, EntityId),
+    Context {
+        source: Box<WorldError>,
+        message: String,
+    },
+}
+
+impl fmt::Display for WorldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorldError::EntityDoesNotExist => write!(f, "entity does not exist"),
+            WorldError::EntityDoesNotHaveComponent(ty) => {
+                write!(f, "entity does not have component {ty:?}")
+            }
+            WorldError::EntityAlreadyHasComponent(ty) => {
+                write!(f, "entity already has component {ty:?}")
+            }
+            WorldError::CreateEntity => write!(f, "could not create entity"),
+            WorldError::DeleteEntity => write!(f, "could not delete entity"),
+            WorldError::ScopeDoesNotExist(scope) => write!(f, "scope {scope} does not exist"),
+            WorldError::ResourceDoesNotExist(ty) => write!(f, "resource {ty:?} does not exist"),
+            WorldError::NoMatchingEntity(ty) => write!(f, "no entity has component {ty:?}"),
+            WorldError::MultipleMatchingEntities(ty) => {
+                write!(f, "more than one entity has component {ty:?}")
+            }
+            WorldError::ParentCycle(child, parent) => {
+                write!(
+                    f,
+                    "making entity {parent} the parent of entity {child} would create a cycle"
+                )
+            }
+            WorldError::Context { source, message } => write!(f, "{message}: {source}"),
+        }
+    }
+}
+
+impl Error for WorldError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            WorldError::Context { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+///
+/// Adds `context` to results, to annotate a `WorldError` with what was
+/// being done when it happened
+/// ```
+/// use enco::custom_errors::*;
+/// use enco::world::*;
+///
+/// let mut world = World::new();
+/// let error = world
+///     .delete_entity(&42)
+///     .context("despawning the player")
+///     .unwrap_err();
+///
+/// assert!(error.to_string().starts_with("despawning the player: "));
+/// ```
+pub trait ResultExt<T> {
+    fn context(self, message: impl Into<String>) -> Result<T, WorldError>;
+}
+
+impl<T> ResultExt<T> for Result<T, WorldError> {
+    fn context(self, message: impl Into<String>) -> Result<T, WorldError> {
+        self.map_err(|source| WorldError::Context {
+            source: Box::new(source),
+            message: message.into(),
+        })
+    }
 }
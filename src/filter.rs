@@ -0,0 +1,87 @@
+use std::any::{Any, TypeId};
+
+use crate::world::ComponentHash;
+
+///
+/// A condition on the components of an entity, checked at runtime. Filters
+/// can be combined with `And`, `Or` and `Not`, and used with
+/// `World::query_filtered`.
+/// ```
+/// use enco::filter::*;
+/// use enco::world::*;
+/// struct Position(i32);
+/// struct Frozen;
+///
+/// let mut world = World::new();
+/// let entity_id = world.create_entity().with(Position(0)).done();
+/// world.create_entity().with(Position(1)).with(Frozen).done();
+///
+/// let filter = And(Box::new(With::of::<Position>()), Box::new(Without::of::<Frozen>()));
+/// let ids: Vec<EntityId> = world.query_filtered(&filter).collect();
+/// assert_eq!(ids, vec![entity_id]);
+/// ```
+pub trait Filter {
+    fn matches(&self, hash: &ComponentHash) -> bool;
+}
+
+///
+/// Matches entities that have a component of the given type
+pub struct With(pub TypeId);
+
+impl With {
+    pub fn of<T: Any>() -> Self {
+        With(TypeId::of::<T>())
+    }
+}
+
+impl Filter for With {
+    fn matches(&self, hash: &ComponentHash) -> bool {
+        hash.contains_key(&self.0)
+    }
+}
+
+///
+/// Matches entities that don't have a component of the given type
+pub struct Without(pub TypeId);
+
+impl Without {
+    pub fn of<T: Any>() -> Self {
+        Without(TypeId::of::<T>())
+    }
+}
+
+impl Filter for Without {
+    fn matches(&self, hash: &ComponentHash) -> bool {
+        !hash.contains_key(&self.0)
+    }
+}
+
+///
+/// Matches entities that match both filters
+pub struct And(pub Box<dyn Filter>, pub Box<dyn Filter>);
+
+impl Filter for And {
+    fn matches(&self, hash: &ComponentHash) -> bool {
+        self.0.matches(hash) && self.1.matches(hash)
+    }
+}
+
+///
+/// Matches entities that match at least one of the filters
+pub struct Or(pub Box<dyn Filter>, pub Box<dyn Filter>);
+
+impl Filter for Or {
+    fn matches(&self, hash: &ComponentHash) -> bool {
+        self.0.matches(hash) || self.1.matches(hash)
+    }
+}
+
+///
+/// Matches entities that don't match the filter
+pub struct Not(pub Box<dyn Filter>);
+
+impl Filter for Not {
+    fn matches(&self, hash: &ComponentHash) -> bool {
+        !self.0.matches(hash)
+    }
+}
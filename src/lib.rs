@@ -1,2 +1,7 @@
+pub mod allocator;
+pub mod command;
+pub mod component;
 pub mod custom_errors;
+pub mod filter;
+pub mod view;
 pub mod world;
@@ -0,0 +1,41 @@
+use std::any::Any;
+
+use crate::world::*;
+
+///
+/// A read-only view of a `World`. It exposes the methods that read
+/// entities and components, but none of the ones that change them.
+#[derive(Clone, Copy)]
+pub struct WorldView<'a> {
+    world: &'a World,
+}
+
+impl<'a> WorldView<'a> {
+    pub fn new(world: &'a World) -> Self {
+        WorldView { world }
+    }
+
+    pub fn num_entities(&self) -> usize {
+        self.world.num_entities()
+    }
+
+    pub fn has_component<T: Any>(&self, entity_id: &EntityId) -> bool {
+        self.world.has_component::<T>(entity_id)
+    }
+
+    pub fn count<T: Any>(&self) -> usize {
+        self.world.count::<T>()
+    }
+
+    pub fn query<T: Any>(&self) -> impl Iterator<Item = &'a T> {
+        self.world.query::<T>()
+    }
+
+    pub fn get_entity_component<T: Any>(&self, entity_id: &EntityId) -> Option<&'a T> {
+        self.world.get_entity_component::<T>(entity_id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'a usize> {
+        self.world.iter()
+    }
+}
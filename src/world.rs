@@ -1,196 +1,4747 @@
 use std::{
     any::{Any, TypeId},
-    borrow::BorrowMut,
-    collections::HashMap,
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    marker::PhantomData,
+    ops::{Deref, Range},
+    rc::Rc,
 };
 
+use crate::allocator::*;
+use crate::component::{Component, ComponentKey};
 use crate::custom_errors::*;
+use crate::filter::Filter;
+use crate::view::WorldView;
 
 pub type EntityId = usize;
 pub type ComponentHash = HashMap<TypeId, Box<dyn Any>>;
+pub type ScopeId = usize;
+
+type HookHash = HashMap<TypeId, Vec<Box<dyn Fn(EntityId)>>>;
+type BatchHook = Box<dyn FnMut(&[TypeId])>;
+type CloneFn = fn(&dyn Any) -> Box<dyn Any>;
+type EqFn = fn(&dyn Any, &dyn Any) -> bool;
+type ResetFn = fn(&mut dyn Any);
+type PruneFn = fn(&mut dyn Any);
+type TagId = usize;
+
+// With the `deterministic` feature, entities are kept sorted by id so that
+// every iteration over them goes in ascending id order. Iterations driven
+// by the per-type index sort its ids first.
+#[cfg(not(feature = "deterministic"))]
+type EntityMap = HashMap<EntityId, ComponentHash>;
+#[cfg(feature = "deterministic")]
+type EntityMap = std::collections::BTreeMap<EntityId, ComponentHash>;
 
 pub struct World {
-    entity_components: HashMap<EntityId, ComponentHash>,
-    new_entity_id: EntityId,
+    entity_components: EntityMap,
+    allocator: Box<dyn IdAllocator>,
+    on_add_hooks: HookHash,
+    on_remove_hooks: HookHash,
+    on_empty: Option<Box<dyn FnMut()>>,
+    on_batch: Option<BatchHook>,
+    batched_types: Option<HashSet<TypeId>>,
+    parents: HashMap<EntityId, EntityId>,
+    children: HashMap<EntityId, Vec<EntityId>>,
+    tag_ids: HashMap<String, TagId>,
+    tagged: HashMap<TagId, HashSet<EntityId>>,
+    entity_tags: HashMap<EntityId, HashSet<TagId>>,
+    component_index: HashMap<TypeId, HashSet<EntityId>>,
+    added_components: HashMap<TypeId, HashSet<EntityId>>,
+    query_cache: RefCell<HashMap<TypeId, Rc<[EntityId]>>>,
+    active_borrows: Cell<usize>,
+    component_order: HashMap<EntityId, Vec<TypeId>>,
+    creation_order: HashMap<EntityId, u64>,
+    next_creation: u64,
+    reserved: HashSet<EntityId>,
+    reserved_blocks: Vec<Range<EntityId>>,
+    is_snapshot: bool,
+    components_per_entity: usize,
+    clone_fns: HashMap<TypeId, CloneFn>,
+    eq_fns: HashMap<TypeId, EqFn>,
+    component_names: HashMap<TypeId, &'static str>,
+    component_sizes: HashMap<TypeId, usize>,
+    component_pools: HashMap<TypeId, ComponentPool>,
+    interned: HashMap<TypeId, InternPool>,
+    change_log: Option<Vec<WorldOp>>,
+    structural_generation: u64,
+    strict_mode: StrictMode,
+    events: HashMap<TypeId, Vec<Box<dyn Any>>>,
+    resources: HashMap<TypeId, Box<dyn Any>>,
+    scopes: HashMap<ScopeId, Vec<(EntityId, TypeId)>>,
+    new_scope_id: ScopeId,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-impl World {
-    pub fn new() -> Self {
-        World {
-            entity_components: HashMap::new(),
-            new_entity_id: 0,
-        }
+impl World {
+    pub fn new() -> Self {
+        Self::with_allocator(MonotonicAllocator::new())
+    }
+
+    ///
+    /// Creates a world that uses the given allocator to pick entity ids
+    /// ```
+    /// use enco::allocator::*;
+    /// use enco::world::*;
+    ///
+    /// let mut world = World::with_allocator(RecyclingAllocator::new());
+    /// ```
+    pub fn with_allocator(allocator: impl IdAllocator + 'static) -> Self {
+        World {
+            entity_components: EntityMap::new(),
+            allocator: Box::new(allocator),
+            on_add_hooks: HashMap::new(),
+            on_remove_hooks: HashMap::new(),
+            on_empty: None,
+            on_batch: None,
+            batched_types: None,
+            parents: HashMap::new(),
+            children: HashMap::new(),
+            tag_ids: HashMap::new(),
+            tagged: HashMap::new(),
+            entity_tags: HashMap::new(),
+            component_index: HashMap::new(),
+            added_components: HashMap::new(),
+            query_cache: RefCell::new(HashMap::new()),
+            active_borrows: Cell::new(0),
+            component_order: HashMap::new(),
+            creation_order: HashMap::new(),
+            next_creation: 0,
+            reserved: HashSet::new(),
+            reserved_blocks: Vec::new(),
+            is_snapshot: false,
+            components_per_entity: 0,
+            clone_fns: HashMap::new(),
+            eq_fns: HashMap::new(),
+            component_names: HashMap::new(),
+            component_sizes: HashMap::new(),
+            component_pools: HashMap::new(),
+            interned: HashMap::new(),
+            change_log: None,
+            structural_generation: 0,
+            strict_mode: StrictMode::default(),
+            events: HashMap::new(),
+            resources: HashMap::new(),
+            scopes: HashMap::new(),
+            new_scope_id: 0,
+        }
+    }
+
+    ///
+    /// Creates a world with room for `entities` entities, whose entities
+    /// start out with room for `components_per_entity` components each, so
+    /// that filling it up doesn't reallocate as often
+    /// ```
+    /// use enco::world::*;
+    /// struct Position(f32, f32);
+    /// struct Velocity(f32, f32);
+    ///
+    /// let mut world = World::with_capacity_for(1000, 2);
+    /// world.create_entity().with(Position(0.0, 0.0)).with(Velocity(1.0, 0.0)).done();
+    /// ```
+    pub fn with_capacity_for(entities: usize, components_per_entity: usize) -> Self {
+        let mut world = World::new();
+        world.reserve_entities(entities);
+        world.components_per_entity = components_per_entity;
+        world
+    }
+
+    ///
+    /// Creates an entity in the current world.
+    /// ```
+    /// use enco::world::*;
+    /// struct MyComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world
+    ///          .create_entity()
+    ///          .with(MyComponent(12))
+    ///          .done();
+    /// ```
+    pub fn create_entity(&mut self) -> EntityBuilder<'_> {
+        self.check_not_iterating();
+        let entity_id = self.allocate_vacant_id();
+        self.entity_spawned(entity_id);
+        EntityBuilder {
+            world: self,
+            entity_id,
+            error: None,
+        }
+    }
+
+    ///
+    /// Returns the id the next call to `create_entity` will give its
+    /// entity, without changing anything. Works on a copy of the allocator,
+    /// so recycled ids and ids that are already taken are accounted for.
+    /// ```
+    /// use enco::allocator::*;
+    /// use enco::world::*;
+    ///
+    /// let mut world = World::with_allocator(RecyclingAllocator::new());
+    /// let entity_id = world.create_entity().done();
+    /// world.delete_entity(&entity_id).unwrap();
+    ///
+    /// assert_eq!(world.next_entity_id(), entity_id);
+    /// ```
+    pub fn next_entity_id(&self) -> EntityId {
+        let mut allocator = self.allocator.clone_allocator();
+        loop {
+            let entity_id = allocator.allocate();
+            if !self.entity_components.contains_key(&entity_id) {
+                return entity_id;
+            }
+        }
+    }
+
+    ///
+    /// Creates an entity with the given id, usually one handed out by
+    /// `reserve_ids`. Fails if an entity with that id already exists.
+    /// ```
+    /// use enco::world::*;
+    /// struct MyComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world
+    ///          .create_entity_with_id(7)
+    ///          .unwrap()
+    ///          .with(MyComponent(12))
+    ///          .done();
+    /// assert_eq!(entity_id, 7);
+    /// ```
+    pub fn create_entity_with_id(
+        &mut self,
+        entity_id: EntityId,
+    ) -> Result<EntityBuilder<'_>, WorldError> {
+        if self.entity_components.contains_key(&entity_id) {
+            return Err(WorldError::CreateEntity);
+        }
+        self.entity_spawned(entity_id);
+        Ok(EntityBuilder {
+            world: self,
+            entity_id,
+            error: None,
+        })
+    }
+
+    ///
+    /// Sets aside a block of consecutive ids that `create_entity` will never
+    /// hand out, so that entities can be created in it later on with
+    /// `create_entity_with_id`. No entity is created. The block stays set
+    /// aside after its entities are deleted, and through `clear`,
+    /// `truncate` and `reindex`.
+    ///
+    /// Panics if the world's allocator can't reserve ids, which is never
+    /// the case for the allocators in this crate.
+    /// ```
+    /// use enco::world::*;
+    ///
+    /// let mut world = World::new();
+    /// let block = world.reserve_ids(10);
+    /// let entity_id = world.create_entity().done();
+    ///
+    /// assert!(!block.contains(&entity_id));
+    /// ```
+    pub fn reserve_ids(&mut self, count: usize) -> Range<EntityId> {
+        let block = self
+            .allocator
+            .reserve(count)
+            .expect("the allocator does not support reserving ids");
+        self.reserved_blocks.push(block.clone());
+        block
+    }
+
+    ///
+    /// Creates an empty entity and returns its id, so that components can be
+    /// added to it later on. Until it gets its first component, the entity
+    /// is left alone by `prune_empty`.
+    /// ```
+    /// use enco::world::*;
+    /// struct MyComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.reserve_entity();
+    /// world.add_component(&entity_id, MyComponent(12)).unwrap();
+    /// ```
+    pub fn reserve_entity(&mut self) -> EntityId {
+        let entity_id = self.create_entity().done();
+        self.reserved.insert(entity_id);
+        entity_id
+    }
+
+    ///
+    /// Creates `N` empty entities and returns their ids, in creation order
+    /// ```
+    /// use enco::world::*;
+    ///
+    /// let mut world = World::new();
+    /// let [parent, child] = world.spawn_n();
+    /// world.set_parent(child, parent).unwrap();
+    /// ```
+    pub fn spawn_n<const N: usize>(&mut self) -> [EntityId; N] {
+        std::array::from_fn(|_| self.create_entity().done())
+    }
+
+    ///
+    /// Creates one entity per pair of components, returning the ids of the
+    /// new entities in order
+    /// ```
+    /// use enco::world::*;
+    /// struct Name(String);
+    /// struct Age(u32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_ids = world.extend(vec![
+    ///     (Name("Alice".to_string()), Age(30)),
+    ///     (Name("Bob".to_string()), Age(25)),
+    /// ]);
+    /// assert_eq!(entity_ids.len(), 2);
+    /// ```
+    pub fn extend<A: Any, B: Any, I: IntoIterator<Item = (A, B)>>(
+        &mut self,
+        iter: I,
+    ) -> Vec<EntityId> {
+        iter.into_iter()
+            .map(|(a, b)| self.create_entity().with(a).with(b).done())
+            .collect()
+    }
+
+    pub fn num_entities(&self) -> usize {
+        self.entity_components.len()
+    }
+
+    ///
+    /// Returns the number of entities, like `num_entities`
+    pub fn len(&self) -> usize {
+        self.num_entities()
+    }
+
+    ///
+    /// Checks whether the world has no entities
+    pub fn is_empty(&self) -> bool {
+        self.entity_components.is_empty()
+    }
+
+    ///
+    /// Returns how many entities the world can hold without reallocating.
+    /// With the `deterministic` feature, entities aren't preallocated and
+    /// this is the same as `len`.
+    /// ```
+    /// use enco::world::*;
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().done();
+    /// world.reserve_entities(10);
+    /// assert!(world.capacity() >= world.len());
+    /// ```
+    pub fn capacity(&self) -> usize {
+        #[cfg(not(feature = "deterministic"))]
+        return self.entity_components.capacity();
+        #[cfg(feature = "deterministic")]
+        return self.entity_components.len();
+    }
+
+    pub fn num_components(&self, entity_id: &EntityId) -> Result<usize, WorldError> {
+        if let Some(components) = self.entity_components.get(entity_id) {
+            return Ok(components.len());
+        }
+        Err(WorldError::EntityDoesNotExist)
+    }
+
+    ///
+    /// Like `num_components`, but returns 0 if the entity doesn't exist
+    /// ```
+    /// use enco::world::*;
+    ///
+    /// let world = World::new();
+    /// assert_eq!(world.component_count_or_zero(&42), 0);
+    /// ```
+    pub fn component_count_or_zero(&self, entity_id: &EntityId) -> usize {
+        self.entity_components
+            .get(entity_id)
+            .map_or(0, HashMap::len)
+    }
+
+    ///
+    /// Lists every entity with its number of components, sorted by that
+    /// number, ascending or descending. Entities with as many components
+    /// are sorted by id, in both directions.
+    /// ```
+    /// use enco::world::*;
+    /// struct Position(i32);
+    /// struct Velocity(i32);
+    ///
+    /// let mut world = World::new();
+    /// let a = world.create_entity().with(Position(0)).done();
+    /// let b = world.create_entity().with(Position(0)).with(Velocity(1)).done();
+    ///
+    /// assert_eq!(world.entities_by_component_count(true), vec![(b, 2), (a, 1)]);
+    /// ```
+    pub fn entities_by_component_count(&self, descending: bool) -> Vec<(EntityId, usize)> {
+        let mut counts: Vec<(EntityId, usize)> = self
+            .entity_components
+            .iter()
+            .map(|(entity_id, components)| (*entity_id, components.len()))
+            .collect();
+        counts.sort_unstable_by(|(a_id, a_count), (b_id, b_count)| {
+            let by_count = if descending {
+                b_count.cmp(a_count)
+            } else {
+                a_count.cmp(b_count)
+            };
+            by_count.then(a_id.cmp(b_id))
+        });
+        counts
+    }
+
+    ///
+    /// Checks whether an entity has a component of the given type
+    /// ```
+    /// use enco::world::*;
+    /// struct SomeComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().with(SomeComponent(1)).done();
+    /// assert!(world.has_component::<SomeComponent>(&entity_id));
+    /// ```
+    pub fn has_component<T: Any>(&self, entity_id: &EntityId) -> bool {
+        self.has_component_by_type(entity_id, TypeId::of::<T>())
+    }
+
+    ///
+    /// Checks whether an entity has a component, given the component's
+    /// `TypeId`. Useful when the component type is only known at runtime.
+    /// ```
+    /// use std::any::TypeId;
+    /// use enco::world::*;
+    /// struct SomeComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().with(SomeComponent(1)).done();
+    /// assert!(world.has_component_by_type(&entity_id, TypeId::of::<SomeComponent>()));
+    /// ```
+    pub fn has_component_by_type(&self, entity_id: &EntityId, ty: TypeId) -> bool {
+        self.entity_components
+            .get(entity_id)
+            .is_some_and(|components| components.contains_key(&ty))
+    }
+
+    ///
+    /// Counts the entities that have a component of the given type
+    /// ```
+    /// use enco::world::*;
+    /// struct SomeComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(SomeComponent(1)).done();
+    /// world.create_entity().done();
+    /// assert_eq!(world.count::<SomeComponent>(), 1);
+    /// ```
+    pub fn count<T: Any>(&self) -> usize {
+        self.component_index
+            .get(&TypeId::of::<T>())
+            .map_or(0, HashSet::len)
+    }
+
+    ///
+    /// Counts the entities that don't have a component of the given type
+    /// ```
+    /// use enco::world::*;
+    /// struct Initialized;
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(Initialized).done();
+    /// world.create_entity().done();
+    /// assert_eq!(world.count_without::<Initialized>(), 1);
+    /// ```
+    pub fn count_without<T: Any>(&self) -> usize {
+        self.num_entities() - self.count::<T>()
+    }
+
+    ///
+    /// Counts, for every component type in the world, how many entities
+    /// have it
+    /// ```
+    /// use std::any::TypeId;
+    /// use enco::world::*;
+    /// struct SomeComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(SomeComponent(1)).done();
+    ///
+    /// let histogram = world.component_histogram();
+    /// assert_eq!(histogram[&TypeId::of::<SomeComponent>()], 1);
+    /// ```
+    pub fn component_histogram(&self) -> HashMap<TypeId, usize> {
+        let mut histogram = HashMap::new();
+        for components in self.entity_components.values() {
+            for type_id in components.keys() {
+                *histogram.entry(*type_id).or_insert(0) += 1;
+            }
+        }
+        histogram
+    }
+
+    ///
+    /// Returns every component type that at least one entity has
+    /// ```
+    /// use std::any::TypeId;
+    /// use enco::world::*;
+    /// struct SomeComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(SomeComponent(1)).done();
+    ///
+    /// assert!(world.all_component_types().contains(&TypeId::of::<SomeComponent>()));
+    /// ```
+    pub fn all_component_types(&self) -> HashSet<TypeId> {
+        self.component_index
+            .iter()
+            .filter(|(_type_id, entities)| !entities.is_empty())
+            .map(|(type_id, _entities)| *type_id)
+            .collect()
+    }
+
+    ///
+    /// Returns the sorted component types of an entity, so that two entities
+    /// with the same set of components have equal archetypes
+    /// ```
+    /// use enco::world::*;
+    /// struct Position(i32);
+    /// struct Velocity(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id_1 = world.create_entity().with(Position(0)).with(Velocity(1)).done();
+    /// let entity_id_2 = world.create_entity().with(Velocity(1)).with(Position(0)).done();
+    ///
+    /// assert_eq!(world.archetype(&entity_id_1), world.archetype(&entity_id_2));
+    /// ```
+    pub fn archetype(&self, entity_id: &EntityId) -> Option<Vec<TypeId>> {
+        let mut type_ids: Vec<TypeId> = self
+            .entity_components
+            .get(entity_id)?
+            .keys()
+            .copied()
+            .collect();
+        type_ids.sort_unstable();
+        Some(type_ids)
+    }
+
+    ///
+    /// Returns the component types of an entity in the order they were
+    /// added. Replacing a component keeps its place, removing it and adding
+    /// it back moves it to the end.
+    /// ```
+    /// use std::any::TypeId;
+    /// use enco::world::*;
+    /// struct Position(i32);
+    /// struct Velocity(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().with(Velocity(1)).with(Position(0)).done();
+    ///
+    /// assert_eq!(
+    ///     world.ordered_component_types(&entity_id),
+    ///     Some(vec![TypeId::of::<Velocity>(), TypeId::of::<Position>()])
+    /// );
+    /// ```
+    pub fn ordered_component_types(&self, entity_id: &EntityId) -> Option<Vec<TypeId>> {
+        if !self.entity_components.contains_key(entity_id) {
+            return None;
+        }
+        Some(
+            self.component_order
+                .get(entity_id)
+                .cloned()
+                .unwrap_or_default(),
+        )
+    }
+
+    ///
+    /// Groups the entity ids by archetype. The ids in each group are sorted.
+    /// ```
+    /// use enco::world::*;
+    /// struct Position(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(Position(0)).done();
+    /// world.create_entity().with(Position(1)).done();
+    /// world.create_entity().done();
+    ///
+    /// assert_eq!(world.archetypes().len(), 2);
+    /// ```
+    pub fn archetypes(&self) -> HashMap<Vec<TypeId>, Vec<EntityId>> {
+        let mut groups: HashMap<Vec<TypeId>, Vec<EntityId>> = HashMap::new();
+        for entity_id in self.entity_components.keys() {
+            if let Some(archetype) = self.archetype(entity_id) {
+                groups.entry(archetype).or_default().push(*entity_id);
+            }
+        }
+        for entity_ids in groups.values_mut() {
+            entity_ids.sort_unstable();
+        }
+        groups
+    }
+
+    ///
+    /// Checks whether at least one entity has a component of the given type
+    /// ```
+    /// use enco::world::*;
+    /// struct SomeComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// assert!(!world.has_any::<SomeComponent>());
+    ///
+    /// world.create_entity().with(SomeComponent(1)).done();
+    /// assert!(world.has_any::<SomeComponent>());
+    /// ```
+    pub fn has_any<T: Any>(&self) -> bool {
+        self.component_index
+            .get(&TypeId::of::<T>())
+            .is_some_and(|entities| !entities.is_empty())
+    }
+
+    ///
+    /// Checks whether any entity has a component of the given type that
+    /// satisfies the predicate. Stops at the first match.
+    /// ```
+    /// use enco::world::*;
+    /// struct Name(String);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(Name("Bob".to_string())).done();
+    ///
+    /// assert!(world.any::<Name, _>(|name| name.0 == "Bob"));
+    /// assert!(!world.any::<Name, _>(|name| name.0 == "Alice"));
+    /// ```
+    pub fn any<T: Any, F: Fn(&T) -> bool>(&self, predicate: F) -> bool {
+        self.query::<T>().any(predicate)
+    }
+
+    ///
+    /// Checks whether every component of the given type satisfies the
+    /// predicate. Entities without the component are not considered, so this
+    /// is true when no entity has it.
+    /// ```
+    /// use enco::world::*;
+    /// struct Health(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(Health(10)).done();
+    /// world.create_entity().with(Health(5)).done();
+    ///
+    /// assert!(world.all::<Health, _>(|health| health.0 > 0));
+    /// ```
+    pub fn all<T: Any, F: Fn(&T) -> bool>(&self, predicate: F) -> bool {
+        self.query::<T>().all(predicate)
+    }
+
+    ///
+    /// Returns an iterator over every component of the given type, in
+    /// ascending entity id order. The matching ids are cached per type until
+    /// a component of that type is added or removed.
+    /// ```
+    /// use enco::world::*;
+    /// struct Health(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(Health(10)).done();
+    ///
+    /// for health in world.query::<Health>() {
+    ///     // Do something here
+    /// }
+    /// ```
+    ///
+    /// The iterator borrows the world, so entities can't be created,
+    /// deleted or changed while it is alive. Collect the ids first and
+    /// change the world afterwards instead. In debug builds, leaking the
+    /// iterator and then calling `create_entity`, `delete_entity` or
+    /// `add_component` panics.
+    /// ```compile_fail
+    /// use enco::world::*;
+    /// struct Health(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(Health(0)).done();
+    ///
+    /// for entity_id in world.iter() {
+    ///     world.delete_entity(entity_id).unwrap();
+    /// }
+    /// ```
+    pub fn query<T: Any>(&self) -> impl Iterator<Item = &T> {
+        let entity_ids = self.cached_query(TypeId::of::<T>());
+        self.track(
+            (0..entity_ids.len())
+                .filter_map(move |i| self.get_entity_component::<T>(&entity_ids[i])),
+        )
+    }
+
+    ///
+    /// Returns the only entity that has a component of the given type, for
+    /// singletons like the player or the camera. Fails if no entity or more
+    /// than one has it.
+    /// ```
+    /// use enco::world::*;
+    /// struct Camera(f32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().with(Camera(1.0)).done();
+    ///
+    /// let (camera_id, camera) = world.single::<Camera>().unwrap();
+    /// assert_eq!((camera_id, camera.0), (entity_id, 1.0));
+    /// ```
+    pub fn single<T: Any>(&self) -> Result<(EntityId, &T), WorldError> {
+        let type_id = TypeId::of::<T>();
+        let entity_id = match *self.cached_query(type_id) {
+            [entity_id] => entity_id,
+            [] => return Err(WorldError::NoMatchingEntity(type_id)),
+            _ => return Err(WorldError::MultipleMatchingEntities(type_id)),
+        };
+        let component = self
+            .get_entity_component::<T>(&entity_id)
+            .ok_or(WorldError::EntityDoesNotHaveComponent(type_id))?;
+        Ok((entity_id, component))
+    }
+
+    ///
+    /// Like `query`, but also returns how many components the iterator will
+    /// yield, so that collections can be sized up front
+    /// ```
+    /// use enco::world::*;
+    /// struct Health(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(Health(10)).done();
+    ///
+    /// let (count, healths) = world.query_counted::<Health>();
+    /// let mut values = Vec::with_capacity(count);
+    /// values.extend(healths.map(|health| health.0));
+    /// assert_eq!(values, vec![10]);
+    /// ```
+    pub fn query_counted<T: Any>(&self) -> (usize, impl Iterator<Item = &T>) {
+        let entity_ids = self.cached_query(TypeId::of::<T>());
+        let count = entity_ids.len();
+        let components =
+            (0..count).filter_map(move |i| self.get_entity_component::<T>(&entity_ids[i]));
+        (count, self.track(components))
+    }
+
+    ///
+    /// Returns an iterator over copies of every component of the given type
+    /// ```
+    /// use enco::world::*;
+    /// #[derive(Clone, Copy)]
+    /// struct Mass(f32);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(Mass(1.5)).done();
+    /// world.create_entity().with(Mass(2.5)).done();
+    ///
+    /// let total: f32 = world.query_copied::<Mass>().map(|mass| mass.0).sum();
+    /// assert_eq!(total, 4.0);
+    /// ```
+    pub fn query_copied<T: Any + Copy>(&self) -> impl Iterator<Item = T> + '_ {
+        self.query::<T>().copied()
+    }
+
+    ///
+    /// Copies every component of the given type into a flat vector, along
+    /// with a vector of the matching entity ids. Both are sorted by id, so
+    /// the value at index `i` belongs to the entity at index `i`.
+    /// ```
+    /// use enco::world::*;
+    /// #[derive(Clone, Copy)]
+    /// struct Mass(f32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().with(Mass(2.0)).done();
+    ///
+    /// let (entity_ids, masses) = world.collect_column::<Mass>();
+    /// assert_eq!(entity_ids, vec![entity_id]);
+    /// assert_eq!(masses[0].0, 2.0);
+    /// ```
+    pub fn collect_column<T: Any + Copy>(&self) -> (Vec<EntityId>, Vec<T>) {
+        self.cached_query(TypeId::of::<T>())
+            .iter()
+            .filter_map(|entity_id| Some((*entity_id, *self.get_entity_component::<T>(entity_id)?)))
+            .unzip()
+    }
+
+    ///
+    /// Folds every component of the given type into a single value, in
+    /// ascending entity id order
+    /// ```
+    /// use enco::world::*;
+    /// struct Health(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(Health(10)).done();
+    /// world.create_entity().with(Health(5)).done();
+    ///
+    /// assert_eq!(world.fold_component(0, |total, health: &Health| total + health.0), 15);
+    /// ```
+    pub fn fold_component<T: Any, B, F: FnMut(B, &T) -> B>(&self, init: B, f: F) -> B {
+        self.query::<T>().fold(init, f)
+    }
+
+    ///
+    /// Calls the closure on every component of the given type, in ascending
+    /// entity id order, along with the entity's id and the world itself, so
+    /// that related components can be looked up from inside the closure
+    /// ```
+    /// use enco::world::*;
+    /// struct Position(i32);
+    /// struct Frozen;
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(Position(0)).with(Frozen).done();
+    /// world.create_entity().with(Position(1)).done();
+    ///
+    /// let mut moving = 0;
+    /// world.for_each_with_world(|world, entity_id, _position: &Position| {
+    ///     if !world.has_component::<Frozen>(&entity_id) {
+    ///         moving += 1;
+    ///     }
+    /// });
+    /// assert_eq!(moving, 1);
+    /// ```
+    pub fn for_each_with_world<T: Any, F: FnMut(&World, EntityId, &T)>(&self, mut f: F) {
+        for entity_id in self.cached_query(TypeId::of::<T>()).iter() {
+            if let Some(component) = self.get_entity_component::<T>(entity_id) {
+                f(self, *entity_id, component);
+            }
+        }
+    }
+
+    pub fn query_mut<T: Any>(&mut self) -> impl Iterator<Item = &mut T> {
+        self.entity_components
+            .values_mut()
+            .filter_map(|components| components.get_mut(&TypeId::of::<T>())?.downcast_mut::<T>())
+    }
+
+    ///
+    /// Returns an iterator that mutably borrows the `A` component of every
+    /// entity that also has a `B` component
+    /// ```
+    /// use enco::world::*;
+    /// struct Velocity(i32);
+    /// struct Alive;
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(Velocity(1)).with(Alive).done();
+    /// world.create_entity().with(Velocity(1)).done();
+    ///
+    /// for velocity in world.query_mut_with::<Velocity, Alive>() {
+    ///     // Only the first entity gets here
+    ///     velocity.0 *= 2;
+    /// }
+    /// ```
+    pub fn query_mut_with<A: Any, B: Any>(&mut self) -> impl Iterator<Item = &mut A> {
+        self.entity_components
+            .values_mut()
+            .filter(|components| components.contains_key(&TypeId::of::<B>()))
+            .filter_map(|components| components.get_mut(&TypeId::of::<A>())?.downcast_mut::<A>())
+    }
+
+    ///
+    /// Returns an iterator over the entities that have both components
+    /// ```
+    /// use enco::world::*;
+    /// struct Position(i32);
+    /// struct Velocity(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(Position(0)).with(Velocity(1)).done();
+    /// world.create_entity().with(Position(5)).done();
+    ///
+    /// for (position, velocity) in world.query2::<Position, Velocity>() {
+    ///     // Only the first entity gets here
+    /// }
+    /// ```
+    pub fn query2<A: Any, B: Any>(&self) -> impl Iterator<Item = (&A, &B)> {
+        let candidates = self.smallest_index(&[TypeId::of::<A>(), TypeId::of::<B>()]);
+        self.track(candidates.filter_map(|entity_id| {
+            let a = self.get_entity_component::<A>(&entity_id)?;
+            let b = self.get_entity_component::<B>(&entity_id)?;
+            Some((a, b))
+        }))
+    }
+
+    ///
+    /// Like `query2`, but also yields the id of each matching entity
+    /// ```
+    /// use enco::world::*;
+    /// struct Position(i32);
+    /// struct Velocity(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(Position(0)).with(Velocity(1)).done();
+    ///
+    /// for (entity_id, position, velocity) in world.join::<Position, Velocity>() {
+    ///     // Do something here
+    /// }
+    /// ```
+    pub fn join<A: Any, B: Any>(&self) -> impl Iterator<Item = (EntityId, &A, &B)> {
+        let candidates = self.smallest_index(&[TypeId::of::<A>(), TypeId::of::<B>()]);
+        self.track(candidates.filter_map(|entity_id| {
+            let a = self.get_entity_component::<A>(&entity_id)?;
+            let b = self.get_entity_component::<B>(&entity_id)?;
+            Some((entity_id, a, b))
+        }))
+    }
+
+    ///
+    /// Returns an iterator that mutably borrows both components of every
+    /// entity that has them. `A` and `B` must be different types: debug
+    /// builds panic otherwise, release builds yield nothing.
+    /// ```
+    /// use enco::world::*;
+    /// struct Position(i32);
+    /// struct Velocity(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(Position(0)).with(Velocity(1)).done();
+    ///
+    /// for (position, velocity) in world.query2_mut::<Position, Velocity>() {
+    ///     position.0 += velocity.0;
+    /// }
+    /// ```
+    pub fn query2_mut<A: Any, B: Any>(&mut self) -> impl Iterator<Item = (&mut A, &mut B)> {
+        debug_assert_distinct::<A, B>();
+        self.entity_components
+            .values_mut()
+            .filter_map(two_components_mut::<A, B>)
+    }
+
+    ///
+    /// Returns an iterator that mutably borrows the `A` component and
+    /// immutably borrows the `B` component of every entity that has both.
+    /// Like `query2_mut`, debug builds panic if `A` and `B` are the same type.
+    /// ```
+    /// use enco::world::*;
+    /// struct Position(i32);
+    /// struct Velocity(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(Position(0)).with(Velocity(1)).done();
+    ///
+    /// for (position, velocity) in world.query_mut_read::<Position, Velocity>() {
+    ///     position.0 += velocity.0;
+    /// }
+    /// ```
+    pub fn query_mut_read<A: Any, B: Any>(&mut self) -> impl Iterator<Item = (&mut A, &B)> {
+        self.query2_mut::<A, B>().map(|(a, b)| (a, &*b))
+    }
+
+    ///
+    /// Calls the closure for every entity that has both components, with
+    /// the first one borrowed mutably. Like `query2_mut`, debug builds panic
+    /// if `A` and `B` are the same type.
+    /// ```
+    /// use enco::world::*;
+    /// struct Position(i32);
+    /// struct Velocity(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().with(Position(0)).with(Velocity(2)).done();
+    ///
+    /// world.integrate::<Position, Velocity, _>(|position, velocity| {
+    ///     position.0 += velocity.0;
+    /// });
+    /// assert_eq!(world.get_entity_component::<Position>(&entity_id).unwrap().0, 2);
+    /// ```
+    pub fn integrate<A: Any, B: Any, F: FnMut(&mut A, &B)>(&mut self, mut f: F) {
+        for (a, b) in self.query2_mut::<A, B>() {
+            f(a, b);
+        }
+    }
+
+    ///
+    /// Calls the closure on every component of the given type and returns
+    /// how many components were visited
+    /// ```
+    /// use enco::world::*;
+    /// struct Position(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().with(Position(3)).done();
+    ///
+    /// let touched = world.map_component::<Position, _>(|position| position.0 *= 2);
+    /// assert_eq!(touched, 1);
+    /// assert_eq!(world.get_entity_component::<Position>(&entity_id).unwrap().0, 6);
+    /// ```
+    pub fn map_component<T: Any, F: FnMut(&mut T)>(&mut self, mut f: F) -> usize {
+        let mut touched = 0;
+        for component in self.query_mut::<T>() {
+            f(component);
+            touched += 1;
+        }
+        touched
+    }
+
+    ///
+    /// Calls the closure on every component of the given type until it
+    /// returns an error, which is then returned, like
+    /// `Iterator::try_for_each`
+    /// ```
+    /// use enco::world::*;
+    /// struct Fuel(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(Fuel(0)).done();
+    ///
+    /// let result = world.try_for_each_mut(|fuel: &mut Fuel| {
+    ///     if fuel.0 == 0 {
+    ///         return Err("out of fuel");
+    ///     }
+    ///     fuel.0 -= 1;
+    ///     Ok(())
+    /// });
+    /// assert_eq!(result, Err("out of fuel"));
+    /// ```
+    pub fn try_for_each_mut<T: Any, E, F: FnMut(&mut T) -> Result<(), E>>(
+        &mut self,
+        f: F,
+    ) -> Result<(), E> {
+        self.entity_components
+            .values_mut()
+            .filter_map(|components| components.get_mut(&TypeId::of::<T>())?.downcast_mut::<T>())
+            .try_for_each(f)
+    }
+
+    ///
+    /// Returns an iterator over the entities that have all three components
+    /// ```
+    /// use enco::world::*;
+    /// struct Position(i32);
+    /// struct Velocity(i32);
+    /// struct Mass(i32);
+    ///
+    /// let mut world = World::new();
+    /// world
+    ///     .create_entity()
+    ///     .with(Position(0))
+    ///     .with(Velocity(1))
+    ///     .with(Mass(2))
+    ///     .done();
+    ///
+    /// for (position, velocity, mass) in world.query3::<Position, Velocity, Mass>() {
+    ///     // Do something here
+    /// }
+    /// ```
+    pub fn query3<A: Any, B: Any, C: Any>(&self) -> impl Iterator<Item = (&A, &B, &C)> {
+        let candidates =
+            self.smallest_index(&[TypeId::of::<A>(), TypeId::of::<B>(), TypeId::of::<C>()]);
+        self.track(candidates.filter_map(|entity_id| {
+            let a = self.get_entity_component::<A>(&entity_id)?;
+            let b = self.get_entity_component::<B>(&entity_id)?;
+            let c = self.get_entity_component::<C>(&entity_id)?;
+            Some((a, b, c))
+        }))
+    }
+
+    ///
+    /// Returns an iterator over the ids of the entities that have at least
+    /// one of the two components
+    /// ```
+    /// use enco::world::*;
+    /// struct Sprite(i32);
+    /// struct Text(String);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(Sprite(0)).done();
+    /// world.create_entity().with(Text("Hello".to_string())).done();
+    /// world.create_entity().done();
+    ///
+    /// for entity_id in world.query_any_of::<Sprite, Text>() {
+    ///     // Draw the entity
+    /// }
+    /// ```
+    pub fn query_any_of<A: Any, B: Any>(&self) -> impl Iterator<Item = EntityId> + '_ {
+        self.entity_components
+            .iter()
+            .filter(|(_entity_id, components)| {
+                components.contains_key(&TypeId::of::<A>())
+                    || components.contains_key(&TypeId::of::<B>())
+            })
+            .map(|(entity_id, _components)| *entity_id)
+    }
+
+    ///
+    /// Returns an iterator over the ids of the entities that lack at least
+    /// one of the two components, the complement of what `query2` matches
+    /// ```
+    /// use enco::world::*;
+    /// struct Position(i32);
+    /// struct Velocity(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(Position(0)).with(Velocity(1)).done();
+    /// let entity_id = world.create_entity().with(Position(0)).done();
+    ///
+    /// for entity_id in world.incomplete::<Position, Velocity>().collect::<Vec<_>>() {
+    ///     world.add_component(&entity_id, Velocity(0)).unwrap();
+    /// }
+    /// assert!(world.has_component::<Velocity>(&entity_id));
+    /// ```
+    pub fn incomplete<A: Any, B: Any>(&self) -> impl Iterator<Item = EntityId> + '_ {
+        self.entity_components
+            .iter()
+            .filter(|(_entity_id, components)| {
+                !components.contains_key(&TypeId::of::<A>())
+                    || !components.contains_key(&TypeId::of::<B>())
+            })
+            .map(|(entity_id, _components)| *entity_id)
+    }
+
+    ///
+    /// Returns the ids of the entities that have a component of every one of
+    /// the given types, sorted. Useful when the types are only known at
+    /// runtime; an empty list matches every entity.
+    /// ```
+    /// use std::any::TypeId;
+    /// use enco::world::*;
+    /// struct Position(i32);
+    /// struct Velocity(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().with(Position(0)).with(Velocity(1)).done();
+    /// world.create_entity().with(Position(0)).done();
+    ///
+    /// let required = [TypeId::of::<Position>(), TypeId::of::<Velocity>()];
+    /// assert_eq!(world.entities_with(&required), vec![entity_id]);
+    /// ```
+    pub fn entities_with(&self, required: &[TypeId]) -> Vec<EntityId> {
+        if required.is_empty() {
+            return self.entity_ids();
+        }
+        let mut entity_ids: Vec<EntityId> = self
+            .smallest_index(required)
+            .filter(|entity_id| {
+                self.entity_components
+                    .get(entity_id)
+                    .is_some_and(|components| required.iter().all(|ty| components.contains_key(ty)))
+            })
+            .collect();
+        entity_ids.sort_unstable();
+        entity_ids
+    }
+
+    ///
+    /// Returns an iterator over every entity id, paired with the entity's
+    /// component of the given type if it has one
+    /// ```
+    /// use enco::world::*;
+    /// struct SomeComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(SomeComponent(1)).done();
+    /// world.create_entity().done();
+    ///
+    /// for (entity_id, component) in world.query_optional::<SomeComponent>() {
+    ///     // component is None for the second entity
+    /// }
+    /// ```
+    pub fn query_optional<T: Any>(&self) -> impl Iterator<Item = (EntityId, Option<&T>)> {
+        self.entity_components
+            .iter()
+            .map(|(entity_id, components)| {
+                let component = components
+                    .get(&TypeId::of::<T>())
+                    .and_then(|component| component.downcast_ref::<T>());
+                (*entity_id, component)
+            })
+    }
+
+    ///
+    /// Returns an iterator over the ids of the entities matching a filter
+    /// built at runtime. The ids are yielded in ascending order.
+    /// ```
+    /// use enco::filter::*;
+    /// use enco::world::*;
+    /// struct Position(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(Position(0)).done();
+    /// world.create_entity().done();
+    ///
+    /// assert_eq!(world.query_filtered(&With::of::<Position>()).count(), 1);
+    /// ```
+    pub fn query_filtered<'a>(
+        &'a self,
+        filter: &'a dyn Filter,
+    ) -> impl Iterator<Item = EntityId> + 'a {
+        self.entity_ids().into_iter().filter(move |entity_id| {
+            self.entity_components
+                .get(entity_id)
+                .is_some_and(|components| filter.matches(components))
+        })
+    }
+
+    ///
+    /// Returns an iterator over the components of the given type whose
+    /// entity id falls in the range, in ascending id order
+    /// ```
+    /// use enco::world::*;
+    /// struct Position(i32);
+    ///
+    /// let mut world = World::new();
+    /// for i in 0..4 {
+    ///     world.create_entity().with(Position(i)).done();
+    /// }
+    ///
+    /// let ids: Vec<EntityId> = world.query_in_range::<Position>(1..3).map(|(id, _)| id).collect();
+    /// assert_eq!(ids, vec![1, 2]);
+    /// ```
+    pub fn query_in_range<T: Any>(
+        &self,
+        range: Range<EntityId>,
+    ) -> impl Iterator<Item = (EntityId, &T)> {
+        let entity_ids = self.cached_query(TypeId::of::<T>());
+        // The ids are sorted, so the ones in range are contiguous
+        let start = entity_ids.partition_point(|entity_id| *entity_id < range.start);
+        (start..entity_ids.len())
+            .map_while(move |i| Some(entity_ids[i]).filter(|entity_id| range.contains(entity_id)))
+            .filter_map(|entity_id| Some((entity_id, self.get_entity_component::<T>(&entity_id)?)))
+    }
+
+    ///
+    /// Returns an iterator over the components of the given type whose
+    /// entity id is `shard` modulo `num_shards`, in ascending id order.
+    /// The shards are disjoint and together cover the whole query, so
+    /// workers can split it up without coordinating. With `num_shards` 0
+    /// there are no shards, so nothing is returned.
+    /// ```
+    /// use enco::world::*;
+    /// struct Task(u32);
+    ///
+    /// let mut world = World::new();
+    /// for i in 0..10 {
+    ///     world.create_entity().with(Task(i)).done();
+    /// }
+    ///
+    /// let ids: Vec<EntityId> = world.query_shard::<Task>(1, 4).map(|(id, _)| id).collect();
+    /// assert_eq!(ids, vec![1, 5, 9]);
+    /// ```
+    pub fn query_shard<T: Any>(
+        &self,
+        shard: usize,
+        num_shards: usize,
+    ) -> impl Iterator<Item = (EntityId, &T)> {
+        let entity_ids = self.cached_query(TypeId::of::<T>());
+        (0..entity_ids.len()).filter_map(move |i| {
+            let entity_id = entity_ids[i];
+            if entity_id.checked_rem(num_shards) != Some(shard) {
+                return None;
+            }
+            Some((entity_id, self.get_entity_component::<T>(&entity_id)?))
+        })
+    }
+
+    ///
+    /// Returns an iterator over the components of the given type, each
+    /// paired with all the components of its entity
+    /// ```
+    /// use std::any::TypeId;
+    /// use enco::world::*;
+    /// struct Position(i32);
+    /// struct Velocity(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(Position(0)).with(Velocity(1)).done();
+    ///
+    /// for (position, components) in world.query_with_siblings::<Position>() {
+    ///     assert!(components.contains_key(&TypeId::of::<Velocity>()));
+    /// }
+    /// ```
+    pub fn query_with_siblings<T: Any>(&self) -> impl Iterator<Item = (&T, &ComponentHash)> {
+        self.entity_components.values().filter_map(|components| {
+            let component = components.get(&TypeId::of::<T>())?.downcast_ref::<T>()?;
+            Some((component, components))
+        })
+    }
+
+    ///
+    /// Collects every component of the given type, sorted by a key computed
+    /// from the component
+    /// ```
+    /// use enco::world::*;
+    /// struct Sprite {
+    ///     z: i32,
+    /// }
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(Sprite { z: 2 }).done();
+    /// world.create_entity().with(Sprite { z: 1 }).done();
+    ///
+    /// for sprite in world.query_sorted_by::<Sprite, _, _>(|sprite| sprite.z) {
+    ///     // Draw back to front
+    /// }
+    /// ```
+    pub fn query_sorted_by<T: Any, K: Ord, F: Fn(&T) -> K>(&self, key: F) -> Vec<&T> {
+        let mut components: Vec<&T> = self.query::<T>().collect();
+        components.sort_by_key(|component| key(component));
+        components
+    }
+
+    ///
+    /// Returns the sorted ids of the entities that have a component of the
+    /// given type, along with a map from each id to its position in the
+    /// list. Handy for laying out parallel arrays.
+    /// ```
+    /// use enco::world::*;
+    /// struct Mass(f32);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().done();
+    /// let entity_id = world.create_entity().with(Mass(1.0)).done();
+    ///
+    /// let (entity_ids, slots) = world.query_dense::<Mass>();
+    /// assert_eq!(entity_ids, vec![entity_id]);
+    /// assert_eq!(slots[&entity_id], 0);
+    /// ```
+    pub fn query_dense<T: Any>(&self) -> (Vec<EntityId>, HashMap<EntityId, usize>) {
+        let entity_ids = self.cached_query(TypeId::of::<T>()).to_vec();
+        let slots = entity_ids
+            .iter()
+            .enumerate()
+            .map(|(slot, entity_id)| (*entity_id, slot))
+            .collect();
+        (entity_ids, slots)
+    }
+
+    ///
+    /// Returns the components of the given type in the order their entities
+    /// were created, which differs from id order once ids get recycled
+    /// ```
+    /// use enco::allocator::*;
+    /// use enco::world::*;
+    /// struct Name(&'static str);
+    ///
+    /// let mut world = World::with_allocator(RecyclingAllocator::new());
+    /// let first = world.create_entity().with(Name("first")).done();
+    /// world.create_entity().with(Name("second")).done();
+    /// world.delete_entity(&first).unwrap();
+    /// world.create_entity().with(Name("third")).done();
+    ///
+    /// let names: Vec<&str> = world.query_in_creation_order::<Name>().iter().map(|name| name.0).collect();
+    /// assert_eq!(names, vec!["second", "third"]);
+    /// ```
+    pub fn query_in_creation_order<T: Any>(&self) -> Vec<&T> {
+        let mut entity_ids = self.cached_query(TypeId::of::<T>()).to_vec();
+        entity_ids.sort_by_key(|entity_id| self.creation_order.get(entity_id));
+        entity_ids
+            .iter()
+            .filter_map(|entity_id| self.get_entity_component::<T>(entity_id))
+            .collect()
+    }
+
+    ///
+    /// Returns the ids of every entity, sorted, along with a mask telling
+    /// whether the entity at the same index has a component of the given
+    /// type
+    /// ```
+    /// use enco::world::*;
+    /// struct Visible;
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(Visible).done();
+    /// world.create_entity().done();
+    ///
+    /// let (_entity_ids, mask) = world.presence_mask::<Visible>();
+    /// assert_eq!(mask, vec![true, false]);
+    /// ```
+    pub fn presence_mask<T: Any>(&self) -> (Vec<EntityId>, Vec<bool>) {
+        let entity_ids = self.entity_ids();
+        let mask = entity_ids
+            .iter()
+            .map(|entity_id| self.has_component::<T>(entity_id))
+            .collect();
+        (entity_ids, mask)
+    }
+
+    ///
+    /// Returns one page of the components of the given type, sorted by
+    /// entity id: `offset` components are skipped and at most `limit` are
+    /// returned
+    /// ```
+    /// use enco::world::*;
+    /// struct Health(i32);
+    ///
+    /// let mut world = World::new();
+    /// for i in 0..5 {
+    ///     world.create_entity().with(Health(i)).done();
+    /// }
+    ///
+    /// let page = world.query_page::<Health>(2, 2);
+    /// assert_eq!(page.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![2, 3]);
+    /// ```
+    pub fn query_page<T: Any>(&self, offset: usize, limit: usize) -> Vec<(EntityId, &T)> {
+        let mut entity_ids: Vec<EntityId> = self
+            .component_index
+            .get(&TypeId::of::<T>())
+            .into_iter()
+            .flatten()
+            .copied()
+            .collect();
+        entity_ids.sort_unstable();
+        entity_ids
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .filter_map(|entity_id| Some((entity_id, self.get_entity_component::<T>(&entity_id)?)))
+            .collect()
+    }
+
+    ///
+    /// Picks one of the entities that have a component of the given type,
+    /// uniformly at random. Returns `None` when no entity has the component.
+    /// ```
+    /// use enco::world::*;
+    /// use rand::SeedableRng;
+    /// struct Spawner(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(Spawner(0)).done();
+    /// world.create_entity().with(Spawner(1)).done();
+    ///
+    /// let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+    /// assert!(world.sample::<Spawner>(&mut rng).is_some());
+    /// ```
+    #[cfg(feature = "rand")]
+    pub fn sample<T: Any>(&self, rng: &mut impl rand::Rng) -> Option<(EntityId, &T)> {
+        self.sample_with::<T>(|n| rng.random_range(0..n))
+    }
+
+    ///
+    /// Like `sample`, without the `rand` feature: `pick` is given the number
+    /// of candidates and must return an index below it. The candidates are
+    /// sorted by id, so a uniform pick samples them uniformly. Returns
+    /// `None` without calling `pick` when no entity has the component.
+    /// Debug builds panic if `pick` returns an index out of range, release
+    /// builds return `None`.
+    /// ```
+    /// use enco::world::*;
+    /// struct Spawner(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(Spawner(0)).done();
+    /// let entity_id = world.create_entity().with(Spawner(1)).done();
+    ///
+    /// let (sampled_id, spawner) = world.sample_with::<Spawner>(|n| n - 1).unwrap();
+    /// assert_eq!((sampled_id, spawner.0), (entity_id, 1));
+    /// ```
+    pub fn sample_with<T: Any>(&self, pick: impl FnOnce(usize) -> usize) -> Option<(EntityId, &T)> {
+        let entity_ids = self.cached_query(TypeId::of::<T>());
+        if entity_ids.is_empty() {
+            return None;
+        }
+        let index = pick(entity_ids.len());
+        debug_assert!(
+            index < entity_ids.len(),
+            "picked index {index} out of {} candidates",
+            entity_ids.len()
+        );
+        let entity_id = *entity_ids.get(index)?;
+        Some((entity_id, self.get_entity_component::<T>(&entity_id)?))
+    }
+
+    ///
+    /// Buckets the ids of the entities that have a component of the given
+    /// type by a key computed from that component. Ids within a bucket are
+    /// sorted.
+    /// ```
+    /// use enco::world::*;
+    /// struct Sprite {
+    ///     texture_id: u32,
+    /// }
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(Sprite { texture_id: 1 }).done();
+    /// world.create_entity().with(Sprite { texture_id: 2 }).done();
+    /// world.create_entity().with(Sprite { texture_id: 1 }).done();
+    ///
+    /// let batches = world.group_by::<Sprite, _, _>(|sprite| sprite.texture_id);
+    /// assert_eq!(batches[&1], vec![0, 2]);
+    /// ```
+    pub fn group_by<T: Any, K: Eq + Hash, F: Fn(&T) -> K>(
+        &self,
+        key: F,
+    ) -> HashMap<K, Vec<EntityId>> {
+        let mut groups: HashMap<K, Vec<EntityId>> = HashMap::new();
+        for entity_id in self.entity_components.keys() {
+            if let Some(component) = self.get_entity_component::<T>(entity_id) {
+                groups.entry(key(component)).or_default().push(*entity_id);
+            }
+        }
+        for entity_ids in groups.values_mut() {
+            entity_ids.sort_unstable();
+        }
+        groups
+    }
+
+    ///
+    /// Starts recording which entities gain a component of the given type,
+    /// for `query_added`. Types aren't tracked by default, so that worlds
+    /// that never poll don't pay for it. Tracking a type again keeps what
+    /// was recorded so far.
+    pub fn track_added<T: Any>(&mut self) {
+        self.added_components.entry(TypeId::of::<T>()).or_default();
+    }
+
+    ///
+    /// Returns the sorted ids of the entities that gained a component of the
+    /// given type since the last call for that type. Entities that lost the
+    /// component again in the meantime are left out. Only additions made
+    /// after `track_added` or a previous call are seen: the first call for
+    /// an untracked type starts tracking it and returns nothing.
+    /// ```
+    /// use enco::world::*;
+    /// struct SomeComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.track_added::<SomeComponent>();
+    /// let entity_id = world.create_entity().with(SomeComponent(1)).done();
+    ///
+    /// assert_eq!(world.query_added::<SomeComponent>(), vec![entity_id]);
+    /// assert!(world.query_added::<SomeComponent>().is_empty());
+    /// ```
+    pub fn query_added<T: Any>(&mut self) -> Vec<EntityId> {
+        let added = self.added_components.entry(TypeId::of::<T>()).or_default();
+        let mut entity_ids: Vec<EntityId> = std::mem::take(added).into_iter().collect();
+        entity_ids.sort_unstable();
+        entity_ids
+    }
+
+    ///
+    /// Deletes an entity from the world. Its children are kept, but
+    /// no longer have a parent.
+    ///
+    /// ```
+    /// use enco::world::*;
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world
+    ///          .create_entity()
+    ///          .done();
+    /// world.delete_entity(&entity_id).unwrap();
+    /// ```
+    ///
+    pub fn delete_entity(&mut self, entity_id: &EntityId) -> Result<(), WorldError> {
+        self.check_not_iterating();
+        if let Some(components) = self.entity_components.remove(entity_id) {
+            self.entity_removed(*entity_id, &components);
+            for (type_id, component) in components {
+                self.recycle(type_id, component);
+            }
+            return Ok(());
+        }
+        Err(WorldError::DeleteEntity)
+    }
+
+    ///
+    /// Deletes every entity in the list that exists, returning how many were
+    /// deleted. Ids of entities that are already gone are ignored.
+    /// ```
+    /// use enco::world::*;
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().done();
+    ///
+    /// assert_eq!(world.despawn_batch(vec![entity_id, 100]), 1);
+    /// ```
+    pub fn despawn_batch<I: IntoIterator<Item = EntityId>>(&mut self, ids: I) -> usize {
+        ids.into_iter()
+            .filter(|entity_id| self.delete_entity(entity_id).is_ok())
+            .count()
+    }
+
+    ///
+    /// Deletes every entity that has no components left, returning how many
+    /// were deleted. Entities made with `reserve_entity` that haven't been
+    /// given a component yet are kept.
+    /// ```
+    /// use enco::world::*;
+    /// struct Bullet;
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().with(Bullet).done();
+    /// world.delete_component::<Bullet>(&entity_id).unwrap();
+    ///
+    /// assert_eq!(world.prune_empty(), 1);
+    /// assert_eq!(world.num_entities(), 0);
+    /// ```
+    pub fn prune_empty(&mut self) -> usize {
+        let empty: Vec<EntityId> = self
+            .entity_components
+            .iter()
+            .filter(|(entity_id, components)| {
+                components.is_empty() && !self.reserved.contains(entity_id)
+            })
+            .map(|(entity_id, _components)| *entity_id)
+            .collect();
+        self.despawn_batch(empty)
+    }
+
+    ///
+    /// Removes an entity from the world and returns all of its components,
+    /// which can be put back with `insert_entity`
+    /// ```
+    /// use enco::world::*;
+    /// struct SomeComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().with(SomeComponent(1)).done();
+    ///
+    /// let components = world.take_entity(&entity_id).unwrap();
+    /// assert_eq!(components.len(), 1);
+    /// assert_eq!(world.num_entities(), 0);
+    /// ```
+    pub fn take_entity(&mut self, entity_id: &EntityId) -> Result<ComponentHash, WorldError> {
+        let components = self
+            .entity_components
+            .remove(entity_id)
+            .ok_or(WorldError::EntityDoesNotExist)?;
+        self.entity_removed(*entity_id, &components);
+        Ok(components)
+    }
+
+    ///
+    /// Removes every entity from the world and returns them with all of
+    /// their components, sorted by id. The ids are handed back to the
+    /// allocator as with `take_entity`, so the default allocator keeps
+    /// counting from where it was; call `clear` first to start over.
+    /// ```
+    /// use enco::world::*;
+    /// struct SomeComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(SomeComponent(1)).done();
+    ///
+    /// let entities = world.drain_entities();
+    /// assert_eq!(entities.len(), 1);
+    /// assert!(world.is_empty());
+    /// ```
+    pub fn drain_entities(&mut self) -> Vec<(EntityId, ComponentHash)> {
+        self.entity_ids()
+            .into_iter()
+            .filter_map(|entity_id| Some((entity_id, self.take_entity(&entity_id).ok()?)))
+            .collect()
+    }
+
+    ///
+    /// Creates an entity out of a set of components, usually taken from
+    /// another entity with `take_entity`, and returns its id
+    /// ```
+    /// use enco::world::*;
+    /// struct SomeComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().with(SomeComponent(1)).done();
+    /// let components = world.take_entity(&entity_id).unwrap();
+    ///
+    /// let mut other_world = World::new();
+    /// let new_id = other_world.insert_entity(components);
+    /// assert!(other_world.has_component::<SomeComponent>(&new_id));
+    /// ```
+    pub fn insert_entity(&mut self, components: ComponentHash) -> EntityId {
+        let entity_id = self.create_entity().done();
+        let type_ids: Vec<TypeId> = components.keys().copied().collect();
+        self.entity_components.insert(entity_id, components);
+        for type_id in type_ids {
+            self.component_added(entity_id, type_id);
+        }
+        entity_id
+    }
+
+    /// Adds a component to an entity. Fails with `EntityDoesNotExist` if
+    /// there is no such entity, and with `EntityAlreadyHasComponent` if it
+    /// already has a component of that type.
+    ///
+    /// ```
+    /// use enco::world::*;
+    /// struct SomeComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world
+    ///          .create_entity()
+    ///          .done();
+    /// world.add_component(&entity_id, SomeComponent(1)).unwrap();
+    /// ```
+    ///
+    pub fn add_component<T: Any>(
+        &mut self,
+        entity_id: &EntityId,
+        component: T,
+    ) -> Result<(), WorldError> {
+        self.check_not_iterating();
+        let type_id = TypeId::of::<T>();
+        if let Some(components_hash) = self.entity_components.get_mut(entity_id) {
+            if components_hash.contains_key(&type_id) {
+                return Err(WorldError::EntityAlreadyHasComponent(type_id));
+            }
+            let boxed = pooled_box(&mut self.component_pools, component);
+            components_hash.insert(type_id, boxed);
+            self.component_added(*entity_id, type_id);
+            return Ok(());
+        }
+        Err(WorldError::EntityDoesNotExist)
+    }
+
+    ///
+    /// Like `add_component`, but mutably borrows the component once it is
+    /// added
+    /// ```
+    /// use enco::world::*;
+    /// struct Inventory(Vec<&'static str>);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().done();
+    ///
+    /// world
+    ///     .add_component_mut(&entity_id, Inventory(vec![]))
+    ///     .unwrap()
+    ///     .0
+    ///     .push("sword");
+    /// ```
+    pub fn add_component_mut<T: Any>(
+        &mut self,
+        entity_id: &EntityId,
+        component: T,
+    ) -> Result<&mut T, WorldError> {
+        self.add_component(entity_id, component)?;
+        self.expect_component_mut::<T>(entity_id)
+    }
+
+    ///
+    /// Adds a component to an entity, replacing the entity's existing
+    /// component of the same type if there is one
+    /// ```
+    /// use enco::world::*;
+    /// struct Health(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().with(Health(10)).done();
+    /// world.set_component(&entity_id, Health(5)).unwrap();
+    ///
+    /// assert_eq!(world.get_entity_component::<Health>(&entity_id).unwrap().0, 5);
+    /// ```
+    pub fn set_component<T: Any>(
+        &mut self,
+        entity_id: &EntityId,
+        component: T,
+    ) -> Result<(), WorldError> {
+        self.replace_component(entity_id, component).map(|_old| ())
+    }
+
+    ///
+    /// Like `set_component`, but hands the replaced component, if any, to
+    /// `teardown` before it is dropped
+    /// ```
+    /// use enco::world::*;
+    /// struct Texture(u32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().with(Texture(1)).done();
+    ///
+    /// let mut freed = vec![];
+    /// world
+    ///     .set_component_with(&entity_id, Texture(2), |old| freed.push(old.0))
+    ///     .unwrap();
+    /// assert_eq!(freed, vec![1]);
+    /// ```
+    pub fn set_component_with<T: Any, F: FnOnce(T)>(
+        &mut self,
+        entity_id: &EntityId,
+        component: T,
+        teardown: F,
+    ) -> Result<(), WorldError> {
+        if let Some(old) = self.replace_component(entity_id, component)? {
+            teardown(old);
+        }
+        Ok(())
+    }
+
+    ///
+    /// Like `set_component`, but returns the component that was replaced,
+    /// if any
+    /// ```
+    /// use enco::world::*;
+    /// struct Health(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().with(Health(10)).done();
+    /// let old = world.replace_component(&entity_id, Health(5)).unwrap();
+    ///
+    /// assert_eq!(old.unwrap().0, 10);
+    /// ```
+    pub fn replace_component<T: Any>(
+        &mut self,
+        entity_id: &EntityId,
+        component: T,
+    ) -> Result<Option<T>, WorldError> {
+        let type_id = TypeId::of::<T>();
+        let components = self
+            .entity_components
+            .get_mut(entity_id)
+            .ok_or(WorldError::EntityDoesNotExist)?;
+        // The existing box is reused, so replacing never allocates
+        if let Some(old) = components
+            .get_mut(&type_id)
+            .and_then(|old| old.downcast_mut::<T>())
+        {
+            return Ok(Some(std::mem::replace(old, component)));
+        }
+        components.insert(type_id, pooled_box(&mut self.component_pools, component));
+        self.component_added(*entity_id, type_id);
+        Ok(None)
+    }
+
+    ///
+    /// Sets the component of each entity in `updates`, adding it or
+    /// replacing the existing one, and returns how many updates were
+    /// applied. Updates are all-or-nothing: if any id doesn't belong to an
+    /// entity, nothing is changed and `EntityDoesNotExist` is returned.
+    /// Later updates to the same entity win.
+    /// ```
+    /// use enco::world::*;
+    /// struct Health(i32);
+    ///
+    /// let mut world = World::new();
+    /// let a = world.create_entity().with(Health(10)).done();
+    /// let b = world.create_entity().done();
+    ///
+    /// assert_eq!(world.sync_component(vec![(a, Health(5)), (b, Health(7))]).unwrap(), 2);
+    /// assert!(world.sync_component(vec![(a, Health(1)), (100, Health(1))]).is_err());
+    /// assert_eq!(world.get_entity_component::<Health>(&a).unwrap().0, 5);
+    /// ```
+    pub fn sync_component<T: Any, I: IntoIterator<Item = (EntityId, T)>>(
+        &mut self,
+        updates: I,
+    ) -> Result<usize, WorldError> {
+        let updates: Vec<(EntityId, T)> = updates.into_iter().collect();
+        if updates
+            .iter()
+            .any(|(entity_id, _component)| !self.entity_components.contains_key(entity_id))
+        {
+            return Err(WorldError::EntityDoesNotExist);
+        }
+        let applied = updates.len();
+        for (entity_id, component) in updates {
+            self.set_component(&entity_id, component)?;
+        }
+        Ok(applied)
+    }
+
+    ///
+    /// Adds a component to an entity, or, if the entity already has one of
+    /// the same type, folds the new value into the existing one
+    /// ```
+    /// use enco::world::*;
+    /// struct Damage(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().done();
+    /// world.merge_component(&entity_id, Damage(3), |total, new| total.0 += new.0).unwrap();
+    /// world.merge_component(&entity_id, Damage(4), |total, new| total.0 += new.0).unwrap();
+    ///
+    /// assert_eq!(world.get_entity_component::<Damage>(&entity_id).unwrap().0, 7);
+    /// ```
+    pub fn merge_component<T: Any, F: Fn(&mut T, T)>(
+        &mut self,
+        entity_id: &EntityId,
+        value: T,
+        combine: F,
+    ) -> Result<(), WorldError> {
+        if !self.entity_components.contains_key(entity_id) {
+            return Err(WorldError::EntityDoesNotExist);
+        }
+        match self.get_entity_component_mut::<T>(entity_id) {
+            Some(existing) => combine(existing, value),
+            None => self.put_component(*entity_id, value),
+        }
+        Ok(())
+    }
+
+    ///
+    /// Deletes a component from an entity
+    ///
+    /// ```
+    /// use enco::world::*;
+    /// struct SomeComponent(i32);
+    /// struct SomeOtherComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world
+    ///          .create_entity()
+    ///          .with(SomeComponent(12))
+    ///          .with(SomeOtherComponent(12))
+    ///          .done();
+    /// world.delete_component::<SomeComponent>(&entity_id).unwrap();
+    /// ```
+    ///
+    pub fn delete_component<T: Any>(&mut self, entity_id: &EntityId) -> Result<(), WorldError> {
+        if let Some(entity_components) = self.entity_components.get_mut(entity_id) {
+            if let Some(component) = entity_components.remove(&TypeId::of::<T>()) {
+                self.recycle(TypeId::of::<T>(), component);
+                self.component_removed(*entity_id, TypeId::of::<T>());
+                return Ok(());
+            }
+            return Err(WorldError::EntityDoesNotHaveComponent(TypeId::of::<T>()));
+        }
+        Err(WorldError::DeleteEntity)
+    }
+
+    ///
+    /// Exchanges the components of the given type between two entities.
+    /// Swapping an entity with itself does nothing.
+    /// ```
+    /// use enco::world::*;
+    /// struct Position(i32);
+    ///
+    /// let mut world = World::new();
+    /// let a = world.create_entity().with(Position(1)).done();
+    /// let b = world.create_entity().with(Position(2)).done();
+    ///
+    /// world.swap_components::<Position>(&a, &b).unwrap();
+    /// assert_eq!(world.get_entity_component::<Position>(&a).unwrap().0, 2);
+    /// ```
+    pub fn swap_components<T: Any>(
+        &mut self,
+        a: &EntityId,
+        b: &EntityId,
+    ) -> Result<(), WorldError> {
+        if a == b {
+            return Ok(());
+        }
+        let type_id = TypeId::of::<T>();
+        if !self.entity_components.contains_key(a) || !self.entity_components.contains_key(b) {
+            return Err(WorldError::EntityDoesNotExist);
+        }
+        if !self.has_component::<T>(a) || !self.has_component::<T>(b) {
+            return Err(WorldError::EntityDoesNotHaveComponent(type_id));
+        }
+        // Both entities were just checked, so the boxes can be moved around
+        // without touching the index
+        let component_a = self
+            .entity_components
+            .get_mut(a)
+            .unwrap()
+            .remove(&type_id)
+            .unwrap();
+        let components_b = self.entity_components.get_mut(b).unwrap();
+        let component_b = components_b.insert(type_id, component_a).unwrap();
+        self.entity_components
+            .get_mut(a)
+            .unwrap()
+            .insert(type_id, component_b);
+        Ok(())
+    }
+
+    ///
+    /// Adds a copy of one entity's component to another entity. Fails if
+    /// either entity doesn't exist, if `from` lacks the component or if `to`
+    /// already has one.
+    /// ```
+    /// use enco::world::*;
+    /// #[derive(Clone)]
+    /// struct Color(u32);
+    ///
+    /// let mut world = World::new();
+    /// let from = world.create_entity().with(Color(0xff0000)).done();
+    /// let to = world.create_entity().done();
+    ///
+    /// world.clone_component::<Color>(&from, &to).unwrap();
+    /// assert_eq!(world.get_entity_component::<Color>(&to).unwrap().0, 0xff0000);
+    /// ```
+    pub fn clone_component<T: Any + Clone>(
+        &mut self,
+        from: &EntityId,
+        to: &EntityId,
+    ) -> Result<(), WorldError> {
+        if !self.entity_components.contains_key(to) {
+            return Err(WorldError::EntityDoesNotExist);
+        }
+        let component = self.expect_component::<T>(from)?.clone();
+        self.add_component(to, component)
+    }
+
+    ///
+    /// Adds a copy of one entity's component to every entity that doesn't
+    /// have a component of that type yet, returning how many entities got
+    /// one. Fails if `source` lacks the component.
+    /// ```
+    /// use enco::world::*;
+    /// #[derive(Clone)]
+    /// struct Theme(&'static str);
+    ///
+    /// let mut world = World::new();
+    /// let source = world.create_entity().with(Theme("dark")).done();
+    /// let entity_id = world.create_entity().done();
+    ///
+    /// assert_eq!(world.broadcast_component::<Theme>(&source).unwrap(), 1);
+    /// assert_eq!(world.get_entity_component::<Theme>(&entity_id).unwrap().0, "dark");
+    /// ```
+    pub fn broadcast_component<T: Any + Clone>(
+        &mut self,
+        source: &EntityId,
+    ) -> Result<usize, WorldError> {
+        let component = self.expect_component::<T>(source)?.clone();
+        let lacking: Vec<EntityId> = self
+            .entity_components
+            .iter()
+            .filter(|(_entity_id, components)| !components.contains_key(&TypeId::of::<T>()))
+            .map(|(entity_id, _components)| *entity_id)
+            .collect();
+        for entity_id in &lacking {
+            self.put_component(*entity_id, component.clone());
+        }
+        Ok(lacking.len())
+    }
+
+    ///
+    /// Removes the component of the given type from every entity, returning
+    /// the components along with the ids of the entities that had them
+    /// ```
+    /// use enco::world::*;
+    /// struct SpawnRequest(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(SpawnRequest(1)).done();
+    /// world.create_entity().with(SpawnRequest(2)).done();
+    ///
+    /// let requests = world.drain::<SpawnRequest>();
+    /// assert_eq!(requests.len(), 2);
+    /// assert_eq!(world.count::<SpawnRequest>(), 0);
+    /// ```
+    pub fn drain<T: Any>(&mut self) -> Vec<(EntityId, T)> {
+        let entity_ids = self
+            .smallest_index(&[TypeId::of::<T>()])
+            .collect::<Vec<_>>();
+        entity_ids
+            .into_iter()
+            .filter_map(|entity_id| Some((entity_id, self.take_component::<T>(&entity_id)?)))
+            .collect()
+    }
+
+    ///
+    /// Replaces the component of type `A` on every entity with a component
+    /// of type `B` built from it, returning how many entities were changed.
+    /// An existing `B` on those entities is overwritten.
+    /// ```
+    /// use enco::world::*;
+    /// struct Celsius(f32);
+    /// struct Fahrenheit(f32);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(Celsius(100.0)).done();
+    ///
+    /// assert_eq!(world.transfer_all(|c: Celsius| Fahrenheit(c.0 * 1.8 + 32.0)), 1);
+    /// assert_eq!(world.query::<Fahrenheit>().next().unwrap().0, 212.0);
+    /// ```
+    pub fn transfer_all<A: Any, B: Any, F: Fn(A) -> B>(&mut self, f: F) -> usize {
+        let drained = self.drain::<A>();
+        let count = drained.len();
+        for (entity_id, component) in drained {
+            self.put_component(entity_id, f(component));
+        }
+        count
+    }
+
+    pub fn get_entity_component<T: Any>(&self, entity_id: &EntityId) -> Option<&T> {
+        let components = self.entity_components.get(entity_id)?;
+        components.get(&TypeId::of::<T>())?.downcast_ref::<T>()
+    }
+
+    pub fn get_entity_component_mut<T: Any>(&mut self, entity_id: &EntityId) -> Option<&mut T> {
+        let components = self.entity_components.get_mut(entity_id)?;
+        components.get_mut(&TypeId::of::<T>())?.downcast_mut::<T>()
+    }
+
+    ///
+    /// Same as `get_entity_component`, with the type given by a key
+    /// ```
+    /// use enco::component::*;
+    /// use enco::world::*;
+    /// struct Health(i32);
+    ///
+    /// let health = ComponentKey::<Health>::new();
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().with(Health(10)).done();
+    ///
+    /// assert_eq!(world.get(&entity_id, health).unwrap().0, 10);
+    /// ```
+    pub fn get<T: Any>(&self, entity_id: &EntityId, _key: ComponentKey<T>) -> Option<&T> {
+        self.get_entity_component::<T>(entity_id)
+    }
+
+    ///
+    /// Same as `get_entity_component_mut`, with the type given by a key
+    pub fn get_mut<T: Any>(
+        &mut self,
+        entity_id: &EntityId,
+        _key: ComponentKey<T>,
+    ) -> Option<&mut T> {
+        self.get_entity_component_mut::<T>(entity_id)
+    }
+
+    ///
+    /// Same as `set_component`, with the type given by a key
+    pub fn set<T: Any>(
+        &mut self,
+        entity_id: &EntityId,
+        _key: ComponentKey<T>,
+        component: T,
+    ) -> Result<(), WorldError> {
+        self.set_component(entity_id, component)
+    }
+
+    ///
+    /// Same as `delete_component`, with the type given by a key
+    pub fn remove<T: Any>(
+        &mut self,
+        entity_id: &EntityId,
+        _key: ComponentKey<T>,
+    ) -> Result<(), WorldError> {
+        self.delete_component::<T>(entity_id)
+    }
+
+    ///
+    /// Like `get_entity_component`, but fails if the entity does not exist,
+    /// so that a deleted entity can be told apart from one that merely lacks
+    /// the component
+    /// ```
+    /// use enco::world::*;
+    /// struct SomeComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().done();
+    /// assert!(world.try_get_component::<SomeComponent>(&entity_id).unwrap().is_none());
+    ///
+    /// world.delete_entity(&entity_id).unwrap();
+    /// assert!(world.try_get_component::<SomeComponent>(&entity_id).is_err());
+    /// ```
+    pub fn try_get_component<T: Any>(
+        &self,
+        entity_id: &EntityId,
+    ) -> Result<Option<&T>, WorldError> {
+        let components = self
+            .entity_components
+            .get(entity_id)
+            .ok_or(WorldError::EntityDoesNotExist)?;
+        Ok(components
+            .get(&TypeId::of::<T>())
+            .and_then(|component| component.downcast_ref::<T>()))
+    }
+
+    ///
+    /// Like `get_entity_component`, but returns an error telling whether the
+    /// entity or the component is missing
+    /// ```
+    /// use enco::world::*;
+    /// use enco::custom_errors::*;
+    /// struct SomeComponent(i32);
+    ///
+    /// fn read(world: &World, entity_id: &EntityId) -> Result<i32, WorldError> {
+    ///     Ok(world.expect_component::<SomeComponent>(entity_id)?.0)
+    /// }
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().with(SomeComponent(1)).done();
+    /// assert_eq!(read(&world, &entity_id).unwrap(), 1);
+    /// ```
+    pub fn expect_component<T: Any>(&self, entity_id: &EntityId) -> Result<&T, WorldError> {
+        let components = self
+            .entity_components
+            .get(entity_id)
+            .ok_or(WorldError::EntityDoesNotExist)?;
+        components
+            .get(&TypeId::of::<T>())
+            .and_then(|component| component.downcast_ref::<T>())
+            .ok_or(WorldError::EntityDoesNotHaveComponent(TypeId::of::<T>()))
+    }
+
+    ///
+    /// Like `get_entity_component_mut`, but returns an error telling whether
+    /// the entity or the component is missing
+    pub fn expect_component_mut<T: Any>(
+        &mut self,
+        entity_id: &EntityId,
+    ) -> Result<&mut T, WorldError> {
+        let components = self
+            .entity_components
+            .get_mut(entity_id)
+            .ok_or(WorldError::EntityDoesNotExist)?;
+        components
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|component| component.downcast_mut::<T>())
+            .ok_or(WorldError::EntityDoesNotHaveComponent(TypeId::of::<T>()))
+    }
+
+    ///
+    /// Mutably borrows a component of an entity, adding the component's
+    /// default value first if the entity doesn't have one
+    /// ```
+    /// use enco::world::*;
+    /// #[derive(Default)]
+    /// struct Counter(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().done();
+    ///
+    /// world.entry_component::<Counter>(&entity_id).unwrap().0 += 1;
+    /// world.entry_component::<Counter>(&entity_id).unwrap().0 += 1;
+    /// assert_eq!(world.get_entity_component::<Counter>(&entity_id).unwrap().0, 2);
+    /// ```
+    pub fn entry_component<T: Any + Default>(
+        &mut self,
+        entity_id: &EntityId,
+    ) -> Result<&mut T, WorldError> {
+        if !self.entity_components.contains_key(entity_id) {
+            return Err(WorldError::EntityDoesNotExist);
+        }
+        if !self.has_component::<T>(entity_id) {
+            self.put_component(*entity_id, T::default());
+        }
+        self.expect_component_mut::<T>(entity_id)
+    }
+
+    ///
+    /// Returns an entry for the component of the given type on an entity,
+    /// to read, insert or modify it in place, like `HashMap::entry`. Fails
+    /// only if the entity doesn't exist.
+    /// ```
+    /// use enco::world::*;
+    /// struct Hits(u32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().done();
+    ///
+    /// for _ in 0..3 {
+    ///     world
+    ///         .component_entry::<Hits>(&entity_id)
+    ///         .unwrap()
+    ///         .and_modify(|hits| hits.0 += 1)
+    ///         .or_insert(Hits(1));
+    /// }
+    /// assert_eq!(world.get_entity_component::<Hits>(&entity_id).unwrap().0, 3);
+    /// ```
+    pub fn component_entry<T: Any>(
+        &mut self,
+        entity_id: &EntityId,
+    ) -> Result<ComponentEntry<'_, T>, WorldError> {
+        if !self.entity_components.contains_key(entity_id) {
+            return Err(WorldError::EntityDoesNotExist);
+        }
+        Ok(ComponentEntry {
+            world: self,
+            entity_id: *entity_id,
+            component: PhantomData,
+        })
+    }
+
+    ///
+    /// Returns a component as `&dyn Any`, given its `TypeId`, so it can be
+    /// downcast by hand
+    /// ```
+    /// use std::any::TypeId;
+    /// use enco::world::*;
+    /// struct SomeComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().with(SomeComponent(1)).done();
+    ///
+    /// let raw = world
+    ///     .get_raw_component(&entity_id, TypeId::of::<SomeComponent>())
+    ///     .unwrap();
+    /// assert_eq!(raw.downcast_ref::<SomeComponent>().unwrap().0, 1);
+    /// ```
+    pub fn get_raw_component(&self, entity_id: &EntityId, ty: TypeId) -> Option<&dyn Any> {
+        let components = self.entity_components.get(entity_id)?;
+        components.get(&ty).map(|component| component.as_ref())
+    }
+
+    ///
+    /// Returns a component as `&mut dyn Any`, given its `TypeId`
+    pub fn get_raw_component_mut(
+        &mut self,
+        entity_id: &EntityId,
+        ty: TypeId,
+    ) -> Option<&mut dyn Any> {
+        let components = self.entity_components.get_mut(entity_id)?;
+        components.get_mut(&ty).map(|component| component.as_mut())
+    }
+
+    ///
+    /// Borrows two components of the same entity at once. Returns `None` if
+    /// the entity doesn't exist or lacks either component.
+    /// ```
+    /// use enco::world::*;
+    /// struct Position(i32);
+    /// struct Velocity(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().with(Position(0)).with(Velocity(2)).done();
+    ///
+    /// let (position, velocity) = world.components_of::<Position, Velocity>(&entity_id).unwrap();
+    /// assert_eq!(position.0 + velocity.0, 2);
+    /// ```
+    pub fn components_of<A: Any, B: Any>(&self, entity_id: &EntityId) -> Option<(&A, &B)> {
+        let a = self.get_entity_component::<A>(entity_id)?;
+        let b = self.get_entity_component::<B>(entity_id)?;
+        Some((a, b))
+    }
+
+    ///
+    /// Mutably borrows two different components of the same entity at once.
+    /// Returns `None` if the entity lacks either component. `A` and `B` must
+    /// be different types: debug builds panic otherwise, release builds
+    /// return `None`.
+    /// ```
+    /// use enco::world::*;
+    /// struct Position(i32);
+    /// struct Velocity(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world
+    ///          .create_entity()
+    ///          .with(Position(0))
+    ///          .with(Velocity(2))
+    ///          .done();
+    ///
+    /// let (position, velocity) = world
+    ///     .get_two_components_mut::<Position, Velocity>(&entity_id)
+    ///     .unwrap();
+    /// position.0 += velocity.0;
+    /// ```
+    pub fn get_two_components_mut<A: Any, B: Any>(
+        &mut self,
+        entity_id: &EntityId,
+    ) -> Option<(&mut A, &mut B)> {
+        debug_assert_distinct::<A, B>();
+        let components = self.entity_components.get_mut(entity_id)?;
+        two_components_mut::<A, B>(components)
+    }
+
+    ///
+    /// Mutably borrows the components of the same type of two different
+    /// entities at once, e.g. to solve a constraint between them. Returns
+    /// `None` if both ids are the same or either entity lacks the component.
+    /// ```
+    /// use enco::world::*;
+    /// struct Position(f32);
+    ///
+    /// let mut world = World::new();
+    /// let a = world.create_entity().with(Position(0.0)).done();
+    /// let b = world.create_entity().with(Position(4.0)).done();
+    ///
+    /// let (position_a, position_b) = world.get_two_entities_component_mut::<Position>(&a, &b).unwrap();
+    /// let midpoint = (position_a.0 + position_b.0) / 2.0;
+    /// position_a.0 = midpoint;
+    /// position_b.0 = midpoint;
+    /// ```
+    pub fn get_two_entities_component_mut<T: Any>(
+        &mut self,
+        a: &EntityId,
+        b: &EntityId,
+    ) -> Option<(&mut T, &mut T)> {
+        if a == b {
+            return None;
+        }
+        #[cfg(not(feature = "deterministic"))]
+        let [components_a, components_b] = self.entity_components.get_disjoint_mut([a, b]);
+        #[cfg(feature = "deterministic")]
+        let (components_a, components_b) = {
+            // BTreeMap can't borrow two values at once, but both ends of a
+            // range can be taken from the same iterator
+            let (low, high) = (a.min(b), a.max(b));
+            let mut range = self.entity_components.range_mut(*low..=*high);
+            let first = range
+                .next()
+                .filter(|(entity_id, _components)| *entity_id == low)
+                .map(|(_entity_id, components)| components);
+            let last = range
+                .next_back()
+                .filter(|(entity_id, _components)| *entity_id == high)
+                .map(|(_entity_id, components)| components);
+            if a < b {
+                (first, last)
+            } else {
+                (last, first)
+            }
+        };
+        let component_a = components_a?
+            .get_mut(&TypeId::of::<T>())?
+            .downcast_mut::<T>()?;
+        let component_b = components_b?
+            .get_mut(&TypeId::of::<T>())?
+            .downcast_mut::<T>()?;
+        Some((component_a, component_b))
+    }
+
+    ///
+    /// Releases the memory held by the world's storage beyond what its
+    /// current entities and components need, e.g. after a mass deletion
+    /// ```
+    /// use enco::world::*;
+    ///
+    /// let mut world = World::new();
+    /// for _ in 0..1000 {
+    ///     world.create_entity().done();
+    /// }
+    /// for entity_id in world.entity_ids() {
+    ///     world.delete_entity(&entity_id).unwrap();
+    /// }
+    /// world.shrink_to_fit();
+    /// ```
+    pub fn shrink_to_fit(&mut self) {
+        #[cfg(not(feature = "deterministic"))]
+        self.entity_components.shrink_to_fit();
+        for components in self.entity_components.values_mut() {
+            components.shrink_to_fit();
+        }
+        let component_names = &self.component_names;
+        self.component_index.retain(|type_id, entities| {
+            !entities.is_empty() || component_names.contains_key(type_id)
+        });
+        self.component_index.shrink_to_fit();
+        for entities in self.component_index.values_mut() {
+            entities.shrink_to_fit();
+        }
+        self.parents.shrink_to_fit();
+        self.children.shrink_to_fit();
+        for pool in self.component_pools.values_mut() {
+            pool.boxes = Vec::new();
+        }
+        for pool in self.interned.values_mut() {
+            (pool.prune)(pool.values.as_mut());
+        }
+    }
+
+    ///
+    /// Like `shrink_to_fit`, but only releases the memory held for one
+    /// component type
+    /// ```
+    /// use enco::world::*;
+    /// struct Particle(f32);
+    ///
+    /// let mut world = World::new();
+    /// for _ in 0..100 {
+    ///     world.create_entity().with(Particle(1.0)).done();
+    /// }
+    /// world.drain::<Particle>();
+    /// world.shrink_component_type::<Particle>();
+    /// ```
+    pub fn shrink_component_type<T: Any>(&mut self) {
+        let type_id = TypeId::of::<T>();
+        if let Some(entities) = self.component_index.get_mut(&type_id) {
+            if entities.is_empty() && !self.component_names.contains_key(&type_id) {
+                self.component_index.remove(&type_id);
+            } else {
+                entities.shrink_to_fit();
+            }
+        }
+        if let Some(entities) = self.added_components.get_mut(&type_id) {
+            entities.shrink_to_fit();
+        }
+        if let Some(pool) = self.component_pools.get_mut(&type_id) {
+            pool.boxes = Vec::new();
+        }
+        self.query_cache.get_mut().remove(&type_id);
+    }
+
+    ///
+    /// Renumbers the entities from 0 up, in the order of their current ids,
+    /// and returns a map from the old ids to the new ones. Ids in blocks set
+    /// aside by `reserve_ids` are skipped, and entities in those blocks keep
+    /// their ids. Relationships and scopes follow the entities, and the
+    /// allocator starts over after the last id.
+    /// ```
+    /// use enco::world::*;
+    ///
+    /// let mut world = World::new();
+    /// for _ in 0..3 {
+    ///     world.create_entity().done();
+    /// }
+    /// world.delete_entity(&1).unwrap();
+    ///
+    /// let remap = world.reindex();
+    /// assert_eq!(remap[&2], 1);
+    /// assert_eq!(world.entity_ids(), vec![0, 1]);
+    /// ```
+    pub fn reindex(&mut self) -> HashMap<EntityId, EntityId> {
+        let mut remap = HashMap::new();
+        let mut next_id = 0;
+        for old_id in self.entity_ids() {
+            if self.in_reserved_block(&old_id) {
+                remap.insert(old_id, old_id);
+                continue;
+            }
+            while let Some(block) = self
+                .reserved_blocks
+                .iter()
+                .find(|block| block.contains(&next_id))
+            {
+                next_id = block.end;
+            }
+            remap.insert(old_id, next_id);
+            next_id += 1;
+        }
+        // Entries for ids that aren't in the world anymore are dropped
+        // rather than renumbered
+        let new_id = |entity_id: &EntityId| remap.get(entity_id).copied();
+
+        self.entity_components = std::mem::take(&mut self.entity_components)
+            .into_iter()
+            .filter_map(|(entity_id, components)| Some((new_id(&entity_id)?, components)))
+            .collect();
+        for entities in self
+            .component_index
+            .values_mut()
+            .chain(self.added_components.values_mut())
+        {
+            *entities = entities.iter().filter_map(new_id).collect();
+        }
+        self.component_order = std::mem::take(&mut self.component_order)
+            .into_iter()
+            .filter_map(|(entity_id, order)| Some((new_id(&entity_id)?, order)))
+            .collect();
+        self.creation_order = std::mem::take(&mut self.creation_order)
+            .into_iter()
+            .filter_map(|(entity_id, creation)| Some((new_id(&entity_id)?, creation)))
+            .collect();
+        self.reserved = self.reserved.iter().filter_map(new_id).collect();
+        self.parents = std::mem::take(&mut self.parents)
+            .into_iter()
+            .filter_map(|(child, parent)| Some((new_id(&child)?, new_id(&parent)?)))
+            .collect();
+        self.children = std::mem::take(&mut self.children)
+            .into_iter()
+            .filter_map(|(parent, children)| {
+                Some((
+                    new_id(&parent)?,
+                    children.iter().filter_map(new_id).collect(),
+                ))
+            })
+            .collect();
+        for scoped in self.scopes.values_mut() {
+            *scoped = scoped
+                .iter()
+                .filter_map(|(entity_id, type_id)| Some((new_id(entity_id)?, *type_id)))
+                .collect();
+        }
+        for entities in self.tagged.values_mut() {
+            *entities = entities.iter().filter_map(new_id).collect();
+        }
+        self.entity_tags = std::mem::take(&mut self.entity_tags)
+            .into_iter()
+            .filter_map(|(entity_id, tags)| Some((new_id(&entity_id)?, tags)))
+            .collect();
+        self.query_cache.get_mut().clear();
+        self.reset_allocator(next_id);
+        remap
+    }
+
+    ///
+    /// Deletes every entity and lets the allocator start over from 0. Ids
+    /// held from before the call may end up pointing to new entities; use
+    /// `clear_preserving_id_counter` if that is a problem.
+    /// ```
+    /// use enco::world::*;
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().done();
+    /// world.clear();
+    ///
+    /// assert_eq!(world.create_entity().done(), 0);
+    /// ```
+    pub fn clear(&mut self) {
+        self.despawn_batch(self.entity_ids());
+        self.reset_allocator(0);
+    }
+
+    ///
+    /// Deletes every entity without giving their ids back to the allocator,
+    /// so that entities created afterwards never reuse an id handed out
+    /// before the call
+    /// ```
+    /// use enco::world::*;
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().done();
+    /// world.clear_preserving_id_counter();
+    ///
+    /// assert_eq!(world.create_entity().done(), 1);
+    /// ```
+    pub fn clear_preserving_id_counter(&mut self) {
+        // The deleted ids are freed into a throwaway allocator
+        let allocator = std::mem::replace(&mut self.allocator, Box::new(MonotonicAllocator::new()));
+        self.despawn_batch(self.entity_ids());
+        self.allocator = allocator;
+    }
+
+    ///
+    /// Deletes every entity whose id is `max_entities` or higher and returns
+    /// how many were deleted. The allocator starts over from `max_entities`.
+    /// ```
+    /// use enco::world::*;
+    ///
+    /// let mut world = World::new();
+    /// for _ in 0..4 {
+    ///     world.create_entity().done();
+    /// }
+    ///
+    /// assert_eq!(world.truncate(2), 2);
+    /// assert_eq!(world.create_entity().done(), 2);
+    /// ```
+    pub fn truncate(&mut self, max_entities: usize) -> usize {
+        let doomed: Vec<EntityId> = self
+            .entity_ids()
+            .into_iter()
+            .filter(|entity_id| *entity_id >= max_entities)
+            .collect();
+        let removed = self.despawn_batch(doomed);
+        self.reset_allocator(max_entities);
+        removed
+    }
+
+    // Relationships
+
+    ///
+    /// Makes `parent` the parent of `child`, replacing any previous parent.
+    /// Fails with `ParentCycle` if `parent` is `child` or one of its
+    /// descendants.
+    /// ```
+    /// use enco::world::*;
+    ///
+    /// let mut world = World::new();
+    /// let parent = world.create_entity().done();
+    /// let child = world.create_entity().done();
+    /// world.set_parent(child, parent).unwrap();
+    ///
+    /// assert_eq!(world.children(&parent), vec![child]);
+    /// ```
+    pub fn set_parent(&mut self, child: EntityId, parent: EntityId) -> Result<(), WorldError> {
+        if !self.entity_components.contains_key(&child)
+            || !self.entity_components.contains_key(&parent)
+        {
+            return Err(WorldError::EntityDoesNotExist);
+        }
+        let mut ancestor = Some(parent);
+        while let Some(current) = ancestor {
+            if current == child {
+                return Err(WorldError::ParentCycle(child, parent));
+            }
+            ancestor = self.parent(&current);
+        }
+        self.detach_from_parent(&child);
+        self.parents.insert(child, parent);
+        self.children.entry(parent).or_default().push(child);
+        Ok(())
+    }
+
+    pub fn parent(&self, child: &EntityId) -> Option<EntityId> {
+        self.parents.get(child).copied()
+    }
+
+    pub fn children(&self, parent: &EntityId) -> Vec<EntityId> {
+        self.children.get(parent).cloned().unwrap_or_default()
+    }
+
+    ///
+    /// Deletes an entity along with all of its descendants
+    /// ```
+    /// use enco::world::*;
+    ///
+    /// let mut world = World::new();
+    /// let parent = world.create_entity().done();
+    /// let child = world.create_entity().done();
+    /// world.set_parent(child, parent).unwrap();
+    ///
+    /// world.despawn_recursive(parent).unwrap();
+    /// assert_eq!(world.num_entities(), 0);
+    /// ```
+    pub fn despawn_recursive(&mut self, entity_id: EntityId) -> Result<(), WorldError> {
+        if !self.entity_components.contains_key(&entity_id) {
+            return Err(WorldError::DeleteEntity);
+        }
+        let mut pending = vec![entity_id];
+        while let Some(current) = pending.pop() {
+            pending.extend(self.children(&current));
+            self.delete_entity(&current)?;
+        }
+        Ok(())
+    }
+
+    ///
+    /// Renders the entities and their parent/child relationships as a
+    /// Graphviz DOT graph, with one node per entity and one edge from each
+    /// parent to each of its children
+    /// ```
+    /// use enco::world::*;
+    ///
+    /// let mut world = World::new();
+    /// let parent = world.create_entity().done();
+    /// let child = world.create_entity().done();
+    /// world.set_parent(child, parent).unwrap();
+    ///
+    /// assert!(world.to_dot().contains("    0 -> 1;"));
+    /// ```
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph world {\n");
+        for entity_id in self.entity_ids() {
+            dot.push_str(&format!("    {entity_id};\n"));
+        }
+        for parent in self.entity_ids() {
+            for child in self.children(&parent) {
+                dot.push_str(&format!("    {parent} -> {child};\n"));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    ///
+    /// Looks for relationships and index entries that point to entities
+    /// that don't exist, returning one error per problem found. A healthy
+    /// world returns no errors.
+    /// ```
+    /// use enco::world::*;
+    ///
+    /// let mut world = World::new();
+    /// let parent = world.create_entity().done();
+    /// let child = world.create_entity().done();
+    /// world.set_parent(child, parent).unwrap();
+    /// world.delete_entity(&parent).unwrap();
+    ///
+    /// assert!(world.validate().is_empty());
+    /// ```
+    pub fn validate(&self) -> Vec<WorldError> {
+        let dangling = |message: String| WorldError::Context {
+            source: Box::new(WorldError::EntityDoesNotExist),
+            message,
+        };
+        let exists = |entity_id: &EntityId| self.entity_components.contains_key(entity_id);
+        let mut problems = Vec::new();
+        for (child, parent) in &self.parents {
+            if !exists(child) || !exists(parent) {
+                problems.push(dangling(format!("{child} is linked to parent {parent}")));
+            }
+        }
+        for (parent, children) in &self.children {
+            for child in children
+                .iter()
+                .filter(|child| !exists(parent) || !exists(child))
+            {
+                problems.push(dangling(format!("{parent} is linked to child {child}")));
+            }
+        }
+        for (type_id, entities) in &self.component_index {
+            for entity_id in entities.iter().filter(|entity_id| !exists(entity_id)) {
+                problems.push(dangling(format!(
+                    "{entity_id} is indexed as having {type_id:?}"
+                )));
+            }
+        }
+        for entity_id in self
+            .component_order
+            .keys()
+            .filter(|entity_id| !exists(entity_id))
+        {
+            problems.push(dangling(format!("{entity_id} has a component order")));
+        }
+        for entity_id in self
+            .creation_order
+            .keys()
+            .filter(|entity_id| !exists(entity_id))
+        {
+            problems.push(dangling(format!("{entity_id} has a creation order")));
+        }
+        for entity_id in self.reserved.iter().filter(|entity_id| !exists(entity_id)) {
+            problems.push(dangling(format!("{entity_id} is reserved")));
+        }
+        for entity_id in self
+            .entity_tags
+            .keys()
+            .filter(|entity_id| !exists(entity_id))
+        {
+            problems.push(dangling(format!("{entity_id} has tags")));
+        }
+        problems
+    }
+
+    ///
+    /// Panics with a descriptive message unless the entity has a component
+    /// of the given type equal to `expected`. Only available with the
+    /// `testing` feature.
+    /// ```
+    /// use enco::world::*;
+    /// #[derive(Debug, PartialEq)]
+    /// struct Health(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().with(Health(10)).done();
+    ///
+    /// # #[cfg(feature = "testing")]
+    /// world.assert_component(&entity_id, &Health(10));
+    /// ```
+    #[cfg(feature = "testing")]
+    pub fn assert_component<T: Any + PartialEq + std::fmt::Debug>(
+        &self,
+        entity_id: &EntityId,
+        expected: &T,
+    ) {
+        let name = self
+            .component_name::<T>()
+            .unwrap_or_else(std::any::type_name::<T>);
+        match self.get_entity_component::<T>(entity_id) {
+            Some(actual) => assert!(
+                actual == expected,
+                "entity {entity_id} has `{name}` {actual:?}, expected {expected:?}"
+            ),
+            None if self.entity_components.contains_key(entity_id) => {
+                panic!("entity {entity_id} has no `{name}` component, expected {expected:?}")
+            }
+            None => panic!("entity {entity_id} does not exist, expected `{name}` {expected:?}"),
+        }
+    }
+
+    fn detach_from_parent(&mut self, child: &EntityId) {
+        if let Some(parent) = self.parents.remove(child) {
+            if let Some(siblings) = self.children.get_mut(&parent) {
+                siblings.retain(|sibling| sibling != child);
+                if siblings.is_empty() {
+                    self.children.remove(&parent);
+                }
+            }
+        }
+    }
+
+    // Tags
+
+    ///
+    /// Tags an entity with a string label. Each distinct label is stored
+    /// once, however many entities carry it.
+    /// ```
+    /// use enco::world::*;
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().done();
+    /// world.add_tag(entity_id, "enemy").unwrap();
+    ///
+    /// assert!(world.has_tag(entity_id, "enemy"));
+    /// assert_eq!(world.entities_with_tag("enemy"), vec![entity_id]);
+    /// ```
+    pub fn add_tag(&mut self, entity_id: EntityId, tag: &str) -> Result<(), WorldError> {
+        if !self.entity_components.contains_key(&entity_id) {
+            return Err(WorldError::EntityDoesNotExist);
+        }
+        let tag_id = match self.tag_ids.get(tag) {
+            Some(tag_id) => *tag_id,
+            None => {
+                let tag_id = self.tag_ids.len();
+                self.tag_ids.insert(tag.to_string(), tag_id);
+                tag_id
+            }
+        };
+        self.tagged.entry(tag_id).or_default().insert(entity_id);
+        self.entity_tags
+            .entry(entity_id)
+            .or_default()
+            .insert(tag_id);
+        Ok(())
+    }
+
+    ///
+    /// Removes a tag from an entity, returning whether the entity had it
+    pub fn remove_tag(&mut self, entity_id: EntityId, tag: &str) -> bool {
+        let Some(tag_id) = self.tag_ids.get(tag) else {
+            return false;
+        };
+        if let Some(tags) = self.entity_tags.get_mut(&entity_id) {
+            tags.remove(tag_id);
+            if tags.is_empty() {
+                self.entity_tags.remove(&entity_id);
+            }
+        }
+        self.tagged
+            .get_mut(tag_id)
+            .is_some_and(|entities| entities.remove(&entity_id))
+    }
+
+    pub fn has_tag(&self, entity_id: EntityId, tag: &str) -> bool {
+        self.tag_ids
+            .get(tag)
+            .and_then(|tag_id| self.tagged.get(tag_id))
+            .is_some_and(|entities| entities.contains(&entity_id))
+    }
+
+    ///
+    /// Returns the sorted ids of the entities that have the given tag
+    pub fn entities_with_tag(&self, tag: &str) -> Vec<EntityId> {
+        let mut entity_ids: Vec<EntityId> = self
+            .tag_ids
+            .get(tag)
+            .and_then(|tag_id| self.tagged.get(tag_id))
+            .into_iter()
+            .flatten()
+            .copied()
+            .collect();
+        entity_ids.sort_unstable();
+        entity_ids
+    }
+
+    ///
+    /// Deletes every entity that has the given tag, returning how many were
+    /// deleted
+    /// ```
+    /// use enco::world::*;
+    ///
+    /// let mut world = World::new();
+    /// let bullet = world.create_entity().done();
+    /// let player = world.create_entity().done();
+    /// world.add_tag(bullet, "projectile").unwrap();
+    ///
+    /// assert_eq!(world.despawn_all_with_tag("projectile"), 1);
+    /// assert_eq!(world.entity_ids(), vec![player]);
+    /// ```
+    pub fn despawn_all_with_tag(&mut self, tag: &str) -> usize {
+        self.despawn_batch(self.entities_with_tag(tag))
+    }
+
+    // Interning
+
+    ///
+    /// Adds an `Interned<T>` component, whose value is shared with every
+    /// other entity that was given an equal value this way, so that
+    /// identical values are only stored once. Values no entity uses anymore
+    /// are released by `shrink_to_fit`.
+    ///
+    /// `Interned<T>` is a different component type from `T`: accessors for
+    /// `T`, like `get_entity_component::<T>` or `query::<T>`, don't see it.
+    /// Read it with `get_interned`, or name `Interned<T>` in the accessors;
+    /// it dereferences to `T`. Fails if the entity already has an
+    /// `Interned<T>`.
+    /// ```
+    /// use enco::world::*;
+    /// #[derive(Clone, PartialEq, Eq, Hash)]
+    /// struct Material(String);
+    ///
+    /// let mut world = World::new();
+    /// let a = world.create_entity().done();
+    /// let b = world.create_entity().done();
+    /// world.add_interned(&a, Material("steel".to_string())).unwrap();
+    /// world.add_interned(&b, Material("steel".to_string())).unwrap();
+    ///
+    /// assert_eq!(world.get_interned::<Material>(&b).unwrap().0, "steel");
+    /// assert_eq!(world.interned_count::<Material>(), 1);
+    /// assert_eq!(world.count::<Interned<Material>>(), 2);
+    /// assert_eq!(world.count::<Material>(), 0);
+    /// ```
+    pub fn add_interned<T: Any + Eq + Hash + Clone>(
+        &mut self,
+        entity_id: &EntityId,
+        component: T,
+    ) -> Result<(), WorldError> {
+        // Checked up front so that a failed add doesn't leave the value
+        // behind in the pool
+        let type_id = TypeId::of::<Interned<T>>();
+        match self.entity_components.get(entity_id) {
+            None => return Err(WorldError::EntityDoesNotExist),
+            Some(components) if components.contains_key(&type_id) => {
+                return Err(WorldError::EntityAlreadyHasComponent(type_id));
+            }
+            Some(_components) => {}
+        }
+        let pool = self
+            .interned
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| InternPool {
+                values: Box::new(HashSet::<Rc<T>>::new()),
+                prune: prune_interned::<T>,
+            });
+        // Pools are keyed by the type of their values
+        let values = pool.values.downcast_mut::<HashSet<Rc<T>>>().unwrap();
+        let shared = match values.get(&component) {
+            Some(shared) => Rc::clone(shared),
+            None => {
+                let shared = Rc::new(component);
+                values.insert(Rc::clone(&shared));
+                shared
+            }
+        };
+        self.add_component(entity_id, Interned(shared))
+    }
+
+    ///
+    /// Returns the value of the `Interned<T>` component added with
+    /// `add_interned`
+    pub fn get_interned<T: Any>(&self, entity_id: &EntityId) -> Option<&T> {
+        self.get_entity_component::<Interned<T>>(entity_id)
+            .map(Deref::deref)
+    }
+
+    ///
+    /// Returns how many distinct values of the given type are stored for
+    /// interned components
+    pub fn interned_count<T: Any + Eq + Hash>(&self) -> usize {
+        self.interned
+            .get(&TypeId::of::<T>())
+            .and_then(|pool| pool.values.downcast_ref::<HashSet<Rc<T>>>())
+            .map_or(0, HashSet::len)
+    }
+
+    // Hooks
+
+    ///
+    /// Registers a callback that runs whenever a component of type `T` is
+    /// added to an entity
+    /// ```
+    /// use enco::world::*;
+    /// struct SomeComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.register_on_add::<SomeComponent>(|entity_id| {
+    ///     println!("Entity {} got a SomeComponent", entity_id);
+    /// });
+    /// world.create_entity().with(SomeComponent(1)).done();
+    /// ```
+    pub fn register_on_add<T: Any>(&mut self, f: impl Fn(EntityId) + 'static) {
+        self.on_add_hooks
+            .entry(TypeId::of::<T>())
+            .or_default()
+            .push(Box::new(f));
+    }
+
+    ///
+    /// Registers a callback that runs whenever a component of type `T` is
+    /// removed from an entity, including when the entity itself is deleted
+    /// ```
+    /// use enco::world::*;
+    /// struct SomeComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.register_on_remove::<SomeComponent>(|entity_id| {
+    ///     println!("Entity {} lost its SomeComponent", entity_id);
+    /// });
+    /// let entity_id = world.create_entity().with(SomeComponent(1)).done();
+    /// world.delete_component::<SomeComponent>(&entity_id).unwrap();
+    /// ```
+    pub fn register_on_remove<T: Any>(&mut self, f: impl Fn(EntityId) + 'static) {
+        self.on_remove_hooks
+            .entry(TypeId::of::<T>())
+            .or_default()
+            .push(Box::new(f));
+    }
+
+    ///
+    /// Sets a callback that runs whenever the last entity of the world is
+    /// deleted, replacing the previous one. It runs once per transition to
+    /// an empty world, so deleting entities from a world that is already
+    /// empty doesn't trigger it.
+    /// ```
+    /// use std::{cell::Cell, rc::Rc};
+    /// use enco::world::*;
+    ///
+    /// let emptied = Rc::new(Cell::new(false));
+    /// let flag = Rc::clone(&emptied);
+    ///
+    /// let mut world = World::new();
+    /// world.set_on_empty(move || flag.set(true));
+    /// world.create_entity().done();
+    /// world.clear();
+    ///
+    /// assert!(emptied.get());
+    /// ```
+    pub fn set_on_empty(&mut self, f: impl FnMut() + 'static) {
+        self.on_empty = Some(Box::new(f));
+    }
+
+    ///
+    /// Sets the callback that `batch_structural` runs once its batch is
+    /// over, replacing the previous one
+    pub fn set_on_batch(&mut self, f: impl FnMut(&[TypeId]) + 'static) {
+        self.on_batch = Some(Box::new(f));
+    }
+
+    ///
+    /// Runs `f` without firing the add and remove hooks for the components
+    /// it adds or removes. Once it returns, the callback set with
+    /// `set_on_batch` runs once with the sorted list of the component types
+    /// that were added or removed. A batch started inside another one is
+    /// part of the outer batch.
+    /// ```
+    /// use std::any::TypeId;
+    /// use enco::world::*;
+    /// struct Sprite(u32);
+    ///
+    /// let mut world = World::new();
+    /// world.set_on_batch(|type_ids| {
+    ///     assert_eq!(type_ids, [TypeId::of::<Sprite>()]);
+    /// });
+    /// world.batch_structural(|world| {
+    ///     for i in 0..100 {
+    ///         world.create_entity().with(Sprite(i)).done();
+    ///     }
+    /// });
+    /// ```
+    pub fn batch_structural(&mut self, f: impl FnOnce(&mut World)) {
+        if self.batched_types.is_some() {
+            f(self);
+            return;
+        }
+        self.batched_types = Some(HashSet::new());
+        let guard = BatchGuard(self);
+        f(guard.0);
+        let mut type_ids: Vec<TypeId> =
+            guard.0.batched_types.take().into_iter().flatten().collect();
+        drop(guard);
+        type_ids.sort_unstable();
+        if let Some(on_batch) = &mut self.on_batch {
+            on_batch(&type_ids);
+        }
+    }
+
+    ///
+    /// Returns a read-only view of the world, which can be handed to code
+    /// that should not create or delete entities
+    /// ```
+    /// use enco::world::*;
+    /// use enco::view::WorldView;
+    /// struct SomeComponent(i32);
+    ///
+    /// fn render(view: WorldView) {
+    ///     for component in view.query::<SomeComponent>() {
+    ///         // Draw the component
+    ///     }
+    /// }
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(SomeComponent(1)).done();
+    /// render(world.view());
+    /// ```
+    pub fn view(&self) -> WorldView<'_> {
+        WorldView::new(self)
+    }
+
+    // Scopes
+
+    ///
+    /// Opens a scope for short-lived components. Components added with
+    /// `add_scoped_component` are all removed together by `end_scope`.
+    /// ```
+    /// use enco::world::*;
+    /// struct PendingDamage(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().done();
+    ///
+    /// let scope = world.begin_scope();
+    /// world.add_scoped_component(scope, &entity_id, PendingDamage(5)).unwrap();
+    /// world.end_scope(scope);
+    ///
+    /// assert!(!world.has_component::<PendingDamage>(&entity_id));
+    /// ```
+    pub fn begin_scope(&mut self) -> ScopeId {
+        let scope = self.new_scope_id;
+        self.new_scope_id += 1;
+        self.scopes.insert(scope, Vec::new());
+        scope
+    }
+
+    ///
+    /// Adds a component to an entity as part of a scope
+    pub fn add_scoped_component<T: Any>(
+        &mut self,
+        scope: ScopeId,
+        entity_id: &EntityId,
+        component: T,
+    ) -> Result<(), WorldError> {
+        if !self.scopes.contains_key(&scope) {
+            return Err(WorldError::ScopeDoesNotExist(scope));
+        }
+        self.add_component(entity_id, component)?;
+        if let Some(added) = self.scopes.get_mut(&scope) {
+            added.push((*entity_id, TypeId::of::<T>()));
+        }
+        Ok(())
+    }
+
+    ///
+    /// Closes a scope, removing every component that was added in it and is
+    /// still around. Returns how many components were removed.
+    pub fn end_scope(&mut self, scope: ScopeId) -> usize {
+        let mut removed = 0;
+        for (entity_id, type_id) in self.scopes.remove(&scope).unwrap_or_default() {
+            let component = self
+                .entity_components
+                .get_mut(&entity_id)
+                .and_then(|components| components.remove(&type_id));
+            if let Some(component) = component {
+                self.recycle(type_id, component);
+                self.component_removed(entity_id, type_id);
+                removed += 1;
+            }
+        }
+        removed
+    }
+
+    // Events
+
+    ///
+    /// Queues an event that isn't attached to any entity. Events stay
+    /// around until `clear_events` is called.
+    /// ```
+    /// use enco::world::*;
+    /// struct CollisionEvent(EntityId, EntityId);
+    ///
+    /// let mut world = World::new();
+    /// world.send_event(CollisionEvent(0, 1));
+    ///
+    /// for collision in world.read_events::<CollisionEvent>() {
+    ///     // Handle the collision
+    /// }
+    /// world.clear_events();
+    /// ```
+    pub fn send_event<E: Any>(&mut self, event: E) {
+        self.events
+            .entry(TypeId::of::<E>())
+            .or_default()
+            .push(Box::new(event));
+    }
+
+    ///
+    /// Returns an iterator over the queued events of the given type, in the
+    /// order they were sent
+    pub fn read_events<E: Any>(&self) -> impl Iterator<Item = &E> {
+        self.events
+            .get(&TypeId::of::<E>())
+            .into_iter()
+            .flatten()
+            .filter_map(|event| event.downcast_ref::<E>())
+    }
+
+    ///
+    /// Discards every queued event
+    pub fn clear_events(&mut self) {
+        self.events.clear();
+    }
+
+    // Resources
+
+    ///
+    /// Stores a value that belongs to the world rather than to an entity,
+    /// replacing any resource of the same type. Returns the world, so that
+    /// insertions can be chained.
+    /// ```
+    /// use enco::world::*;
+    /// struct Gravity(f32);
+    /// struct FrameCount(u64);
+    ///
+    /// let mut world = World::new();
+    /// world.insert_resource(Gravity(9.8)).insert_resource(FrameCount(0));
+    ///
+    /// assert_eq!(world.resource::<Gravity>().unwrap().0, 9.8);
+    /// ```
+    pub fn insert_resource<R: Any>(&mut self, resource: R) -> &mut Self {
+        self.resources.insert(TypeId::of::<R>(), Box::new(resource));
+        self
+    }
+
+    pub fn resource<R: Any>(&self) -> Option<&R> {
+        self.resources.get(&TypeId::of::<R>())?.downcast_ref::<R>()
+    }
+
+    pub fn resource_mut<R: Any>(&mut self) -> Option<&mut R> {
+        self.resources
+            .get_mut(&TypeId::of::<R>())?
+            .downcast_mut::<R>()
+    }
+
+    ///
+    /// Borrows a resource along with every component of the given type,
+    /// mutably. Returns `None` if the resource doesn't exist.
+    /// ```
+    /// use enco::world::*;
+    /// struct DeltaTime(f32);
+    /// struct Position(f32);
+    ///
+    /// let mut world = World::new();
+    /// world.insert_resource(DeltaTime(0.5));
+    /// let entity_id = world.create_entity().with(Position(1.0)).done();
+    ///
+    /// let (delta_time, positions) = world.resource_and_query_mut::<DeltaTime, Position>().unwrap();
+    /// for position in positions {
+    ///     position.0 += delta_time.0;
+    /// }
+    /// assert_eq!(world.get_entity_component::<Position>(&entity_id).unwrap().0, 1.5);
+    /// ```
+    pub fn resource_and_query_mut<R: Any, T: Any>(
+        &mut self,
+    ) -> Option<(&R, impl Iterator<Item = &mut T>)> {
+        let resource = self
+            .resources
+            .get(&TypeId::of::<R>())?
+            .downcast_ref::<R>()?;
+        let components = self
+            .entity_components
+            .values_mut()
+            .filter_map(|components| components.get_mut(&TypeId::of::<T>())?.downcast_mut::<T>());
+        Some((resource, components))
+    }
+
+    ///
+    /// Folds every component of type `T` into the resource `R`, in no
+    /// particular order. Fails if the resource doesn't exist.
+    /// ```
+    /// use enco::world::*;
+    /// struct Mass(f32);
+    /// struct TotalMass(f32);
+    ///
+    /// let mut world = World::new();
+    /// world.insert_resource(TotalMass(0.0));
+    /// world.create_entity().with(Mass(1.5)).done();
+    /// world.create_entity().with(Mass(2.5)).done();
+    ///
+    /// world.reduce_into_resource(|total: &mut TotalMass, mass: &Mass| total.0 += mass.0).unwrap();
+    /// assert_eq!(world.resource::<TotalMass>().unwrap().0, 4.0);
+    /// ```
+    pub fn reduce_into_resource<T: Any, R: Any, F: Fn(&mut R, &T)>(
+        &mut self,
+        f: F,
+    ) -> Result<(), WorldError> {
+        let resource = self
+            .resources
+            .get_mut(&TypeId::of::<R>())
+            .and_then(|resource| resource.downcast_mut::<R>())
+            .ok_or(WorldError::ResourceDoesNotExist(TypeId::of::<R>()))?;
+        let components = self
+            .entity_components
+            .values()
+            .filter_map(|components| components.get(&TypeId::of::<T>())?.downcast_ref::<T>());
+        for component in components {
+            f(resource, component);
+        }
+        Ok(())
+    }
+
+    ///
+    /// Removes a resource from the world, returning it
+    pub fn remove_resource<R: Any>(&mut self) -> Option<R> {
+        let resource = self.resources.remove(&TypeId::of::<R>())?;
+        resource.downcast::<R>().ok().map(|resource| *resource)
+    }
+
+    ///
+    /// Moves the resources of another world into this one. When both worlds
+    /// have a resource of the same type, `overwrite` decides which one is
+    /// kept; a resource that isn't moved stays in the other world.
+    /// ```
+    /// use enco::world::*;
+    /// struct Gravity(f32);
+    ///
+    /// let mut world = World::new();
+    /// let mut other = World::new();
+    /// other.insert_resource(Gravity(9.8));
+    /// world.merge_resources(&mut other, false);
+    ///
+    /// assert_eq!(world.resource::<Gravity>().unwrap().0, 9.8);
+    /// assert!(other.resource::<Gravity>().is_none());
+    /// ```
+    pub fn merge_resources(&mut self, other: &mut World, overwrite: bool) {
+        let resources = std::mem::take(&mut other.resources);
+        for (type_id, resource) in resources {
+            if overwrite || !self.resources.contains_key(&type_id) {
+                self.resources.insert(type_id, resource);
+            } else {
+                other.resources.insert(type_id, resource);
+            }
+        }
+    }
+
+    ///
+    /// Copies every resource whose type was registered with
+    /// `register_cloneable`. Resources of other types are left out.
+    /// ```
+    /// use enco::world::*;
+    /// #[derive(Clone)]
+    /// struct Gravity(f32);
+    ///
+    /// let mut world = World::new();
+    /// world.register_cloneable::<Gravity>();
+    /// world.insert_resource(Gravity(9.8));
+    /// let snapshot = world.snapshot_resources();
+    ///
+    /// world.resource_mut::<Gravity>().unwrap().0 = 1.6;
+    /// world.restore_resources(&snapshot);
+    /// assert_eq!(world.resource::<Gravity>().unwrap().0, 9.8);
+    /// ```
+    pub fn snapshot_resources(&self) -> ResourceSnapshot {
+        let resources = self
+            .resources
+            .iter()
+            .filter_map(|(type_id, resource)| {
+                let clone_fn = *self.clone_fns.get(type_id)?;
+                Some((*type_id, (clone_fn(resource.as_ref()), clone_fn)))
+            })
+            .collect();
+        ResourceSnapshot { resources }
+    }
+
+    ///
+    /// Overwrites the resources held in the snapshot with copies of their
+    /// snapshotted values. Resources the snapshot doesn't hold are left as
+    /// they are, and the snapshot can be restored again later.
+    pub fn restore_resources(&mut self, snapshot: &ResourceSnapshot) {
+        for (type_id, (resource, clone_fn)) in &snapshot.resources {
+            self.resources.insert(*type_id, clone_fn(resource.as_ref()));
+        }
+    }
+
+    // Change log
+
+    ///
+    /// Starts recording every structural change to the world. Recording is
+    /// off by default; enabling it again keeps the ops logged so far.
+    /// ```
+    /// use std::any::TypeId;
+    /// use enco::world::*;
+    /// struct SomeComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.enable_change_log();
+    /// let entity_id = world.create_entity().with(SomeComponent(1)).done();
+    ///
+    /// assert_eq!(
+    ///     world.change_log(),
+    ///     &[
+    ///         WorldOp::Spawn(entity_id),
+    ///         WorldOp::Add(entity_id, TypeId::of::<SomeComponent>()),
+    ///     ]
+    /// );
+    /// ```
+    pub fn enable_change_log(&mut self) {
+        self.change_log.get_or_insert_with(Vec::new);
+    }
+
+    ///
+    /// Returns the structural changes recorded so far, oldest first
+    pub fn change_log(&self) -> &[WorldOp] {
+        self.change_log.as_deref().unwrap_or_default()
+    }
+
+    ///
+    /// Discards the recorded changes, leaving recording enabled if it was
+    pub fn clear_change_log(&mut self) {
+        if let Some(change_log) = &mut self.change_log {
+            change_log.clear();
+        }
+    }
+
+    ///
+    /// Returns a counter that goes up on every entity creation or deletion
+    /// and every component addition or removal, whether or not the change
+    /// log is enabled. Changing the value of an existing component leaves it
+    /// as is, so comparing it across frames tells whether anything derived
+    /// from the world's layout needs to be rebuilt.
+    /// ```
+    /// use enco::world::*;
+    /// struct Health(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().done();
+    /// let generation = world.structural_generation();
+    ///
+    /// world.add_component(&entity_id, Health(10)).unwrap();
+    /// assert!(world.structural_generation() > generation);
+    /// ```
+    pub fn structural_generation(&self) -> u64 {
+        self.structural_generation
+    }
+
+    // Registry
+
+    ///
+    /// Registers a component type with the world, recording its name. The
+    /// type's presence index is created up front, so `reserve_entities` can
+    /// size it ahead of time.
+    /// ```
+    /// use enco::component::*;
+    /// use enco::world::*;
+    ///
+    /// struct Health(i32);
+    /// impl Component for Health {}
+    ///
+    /// let mut world = World::new();
+    /// world.register_component::<Health>();
+    /// ```
+    pub fn register_component<T: Component>(&mut self) {
+        self.component_names.insert(TypeId::of::<T>(), T::name());
+        self.component_index.entry(TypeId::of::<T>()).or_default();
+    }
+
+    ///
+    /// Reserves room for at least `additional` more entities, both in the
+    /// entity storage and in the presence index of every registered type
+    /// ```
+    /// use enco::component::*;
+    /// use enco::world::*;
+    ///
+    /// struct Health(i32);
+    /// impl Component for Health {}
+    ///
+    /// let mut world = World::new();
+    /// world.register_component::<Health>();
+    /// world.reserve_entities(1000);
+    /// ```
+    pub fn reserve_entities(&mut self, additional: usize) {
+        #[cfg(not(feature = "deterministic"))]
+        self.entity_components.reserve(additional);
+        for type_id in self.component_names.keys() {
+            if let Some(entities) = self.component_index.get_mut(type_id) {
+                entities.reserve(additional);
+            }
+        }
+    }
+
+    ///
+    /// Records the size of a component type, so that it is counted by
+    /// `memory_estimate`
+    /// ```
+    /// use enco::world::*;
+    /// struct Health(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.register_sized_component::<Health>();
+    /// ```
+    pub fn register_sized_component<T: Any>(&mut self) {
+        self.component_sizes
+            .insert(TypeId::of::<T>(), std::mem::size_of::<T>());
+    }
+
+    ///
+    /// Estimates how many bytes the components of the registered sized
+    /// types take up. Only the inline size of each component is counted,
+    /// not what it allocates on the heap.
+    /// ```
+    /// use enco::world::*;
+    /// struct Health(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.register_sized_component::<Health>();
+    /// world.create_entity().with(Health(10)).done();
+    ///
+    /// assert_eq!(world.memory_estimate(), 4);
+    /// ```
+    pub fn memory_estimate(&self) -> usize {
+        self.component_sizes
+            .iter()
+            .map(|(type_id, size)| size * self.component_index.get(type_id).map_or(0, HashSet::len))
+            .sum()
+    }
+
+    ///
+    /// Returns the inline size of an entity's component of type `T`, or
+    /// `None` if the entity doesn't have one. Like `memory_estimate`, heap
+    /// allocations owned by the component aren't counted.
+    /// ```
+    /// use enco::world::*;
+    /// struct Health(i32);
+    /// struct Name(String);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().with(Health(10)).done();
+    ///
+    /// assert_eq!(world.component_size::<Health>(&entity_id), Some(4));
+    /// assert_eq!(world.component_size::<Name>(&entity_id), None);
+    /// ```
+    pub fn component_size<T: Any>(&self, entity_id: &EntityId) -> Option<usize> {
+        self.has_component::<T>(entity_id)
+            .then(std::mem::size_of::<T>)
+    }
+
+    ///
+    /// Sets how the world reacts to builder misuse. Worlds are strict by
+    /// default.
+    /// ```
+    /// use enco::world::*;
+    ///
+    /// let mut world = World::new();
+    /// world.set_strict_mode(StrictMode::Lenient);
+    /// assert_eq!(world.strict_mode(), StrictMode::Lenient);
+    /// ```
+    pub fn set_strict_mode(&mut self, strict_mode: StrictMode) {
+        self.strict_mode = strict_mode;
+    }
+
+    pub fn strict_mode(&self) -> StrictMode {
+        self.strict_mode
+    }
+
+    ///
+    /// Returns the name of a registered component type
+    pub fn component_name<T: Any>(&self) -> Option<&'static str> {
+        self.component_names.get(&TypeId::of::<T>()).copied()
+    }
+
+    ///
+    /// Returns the name of a registered component type, given its `TypeId`,
+    /// for readable diagnostics when the type is only known at runtime
+    /// ```
+    /// use std::any::TypeId;
+    /// use enco::component::*;
+    /// use enco::world::*;
+    ///
+    /// struct Health(i32);
+    /// impl Component for Health {
+    ///     fn name() -> &'static str {
+    ///         "Health"
+    ///     }
+    /// }
+    ///
+    /// let mut world = World::new();
+    /// world.register_component::<Health>();
+    /// assert_eq!(world.type_tag(TypeId::of::<Health>()), Some("Health"));
+    /// ```
+    pub fn type_tag(&self, ty: TypeId) -> Option<&str> {
+        self.component_names.get(&ty).copied()
+    }
+
+    ///
+    /// Returns the component types registered with `register_component`
+    /// that no entity currently has, sorted
+    /// ```
+    /// use enco::component::*;
+    /// use enco::world::*;
+    /// struct Legacy;
+    /// impl Component for Legacy {}
+    ///
+    /// let mut world = World::new();
+    /// world.register_component::<Legacy>();
+    ///
+    /// assert_eq!(world.unused_registered_types().len(), 1);
+    /// ```
+    pub fn unused_registered_types(&self) -> Vec<TypeId> {
+        let mut unused: Vec<TypeId> = self
+            .component_names
+            .keys()
+            .filter(|type_id| {
+                self.component_index
+                    .get(type_id)
+                    .is_none_or(HashSet::is_empty)
+            })
+            .copied()
+            .collect();
+        unused.sort_unstable();
+        unused
+    }
+
+    ///
+    /// Keeps up to `capacity` boxes of deleted components of the given type
+    /// around, so that adding a component of that type later on reuses one
+    /// of them instead of allocating. Boxes are pooled whenever a component
+    /// is deleted, whether on its own, with its entity or by `end_scope`;
+    /// replacing a component reuses its box in place. Pooled values are
+    /// reset to their default. A capacity of zero empties the pool.
+    /// ```
+    /// use enco::world::*;
+    /// #[derive(Default)]
+    /// struct Mesh(Vec<f32>);
+    ///
+    /// let mut world = World::new();
+    /// world.set_pool_capacity::<Mesh>(16);
+    ///
+    /// let entity_id = world.create_entity().with(Mesh(vec![1.0])).done();
+    /// world.delete_component::<Mesh>(&entity_id).unwrap();
+    /// assert_eq!(world.pool_len::<Mesh>(), 1);
+    /// ```
+    pub fn set_pool_capacity<T: Any + Default>(&mut self, capacity: usize) {
+        let pool = self
+            .component_pools
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| ComponentPool {
+                capacity,
+                reset: reset_boxed::<T>,
+                boxes: Vec::new(),
+            });
+        pool.capacity = capacity;
+        pool.boxes.truncate(capacity);
+    }
+
+    ///
+    /// Returns how many boxes of the given component type are pooled
+    pub fn pool_len<T: Any>(&self) -> usize {
+        self.component_pools
+            .get(&TypeId::of::<T>())
+            .map_or(0, |pool| pool.boxes.len())
+    }
+
+    // Cloning
+
+    ///
+    /// Registers a component type as cloneable, so that it is carried over
+    /// by `snapshot` and `clone_selection`. Components of unregistered types
+    /// are left behind.
+    /// ```
+    /// use enco::world::*;
+    /// #[derive(Clone)]
+    /// struct SomeComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.register_cloneable::<SomeComponent>();
+    /// ```
+    pub fn register_cloneable<T: Any + Clone>(&mut self) {
+        self.clone_fns.insert(TypeId::of::<T>(), clone_boxed::<T>);
+    }
+
+    ///
+    /// Creates a copy of the world, keeping the entity ids, relationships
+    /// and every component of a registered cloneable type
+    /// ```
+    /// use enco::world::*;
+    /// #[derive(Clone)]
+    /// struct SomeComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.register_cloneable::<SomeComponent>();
+    /// let entity_id = world.create_entity().with(SomeComponent(1)).done();
+    ///
+    /// let snapshot = world.snapshot();
+    /// world.get_entity_component_mut::<SomeComponent>(&entity_id).unwrap().0 = 2;
+    ///
+    /// assert_eq!(snapshot.get_entity_component::<SomeComponent>(&entity_id).unwrap().0, 1);
+    /// ```
+    pub fn snapshot(&self) -> World {
+        let mut snapshot = self.empty_clone();
+        for entity_id in self.entity_components.keys() {
+            self.clone_entity_into(&mut snapshot, entity_id, *entity_id);
+        }
+        snapshot.allocator = self.allocator.clone_allocator();
+        snapshot.parents = self.parents.clone();
+        snapshot.children = self.children.clone();
+        snapshot.tag_ids = self.tag_ids.clone();
+        snapshot.tagged = self.tagged.clone();
+        snapshot.entity_tags = self.entity_tags.clone();
+        snapshot.creation_order = self.creation_order.clone();
+        snapshot.reserved = self.reserved.clone();
+        snapshot.reserved_blocks = self.reserved_blocks.clone();
+        snapshot.next_creation = self.next_creation;
+        snapshot.is_snapshot = true;
+        snapshot
+    }
+
+    ///
+    /// Copies the given entities into a new world, returning it along with
+    /// a map from the original entity ids to the ones in the new world.
+    /// Ids that don't exist are skipped. Relationships between selected
+    /// entities are kept, the ones leaving the selection are cut.
+    /// ```
+    /// use enco::world::*;
+    /// #[derive(Clone)]
+    /// struct SomeComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.register_cloneable::<SomeComponent>();
+    /// world.create_entity().with(SomeComponent(1)).done();
+    /// let entity_id = world.create_entity().with(SomeComponent(2)).done();
+    ///
+    /// let (selection, id_map) = world.clone_selection([entity_id]);
+    /// assert_eq!(selection.num_entities(), 1);
+    /// assert_eq!(
+    ///     selection.get_entity_component::<SomeComponent>(&id_map[&entity_id]).unwrap().0,
+    ///     2
+    /// );
+    /// ```
+    pub fn clone_selection<I: IntoIterator<Item = EntityId>>(
+        &self,
+        ids: I,
+    ) -> (World, HashMap<EntityId, EntityId>) {
+        let mut selection = self.empty_clone();
+        let mut id_map = HashMap::new();
+        for entity_id in ids {
+            if !self.entity_components.contains_key(&entity_id) || id_map.contains_key(&entity_id) {
+                continue;
+            }
+            let new_id = selection.allocator.allocate();
+            self.clone_entity_into(&mut selection, &entity_id, new_id);
+            id_map.insert(entity_id, new_id);
+        }
+        for (parent, new_parent) in &id_map {
+            for child in self.children.get(parent).into_iter().flatten() {
+                if let Some(new_child) = id_map.get(child) {
+                    selection.parents.insert(*new_child, *new_parent);
+                    selection
+                        .children
+                        .entry(*new_parent)
+                        .or_default()
+                        .push(*new_child);
+                }
+            }
+        }
+        (selection, id_map)
+    }
+
+    ///
+    /// Moves the entities matching the predicate out of the world and into
+    /// a new one, keeping their ids. Relationships between moved entities
+    /// are kept, the ones crossing the two worlds are cut.
+    /// ```
+    /// use enco::world::*;
+    /// struct SomeComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.create_entity().with(SomeComponent(1)).done();
+    /// let entity_id = world.create_entity().done();
+    ///
+    /// let empty = world.split(|_entity_id, components| components.is_empty());
+    /// assert_eq!(world.num_entities(), 1);
+    /// assert_eq!(empty.entity_ids(), vec![entity_id]);
+    /// ```
+    pub fn split<F: Fn(EntityId, &ComponentHash) -> bool>(&mut self, f: F) -> World {
+        let mut moved: Vec<EntityId> = self
+            .entity_components
+            .iter()
+            .filter(|(entity_id, components)| f(**entity_id, components))
+            .map(|(entity_id, _components)| *entity_id)
+            .collect();
+        moved.sort_unstable();
+        let parents: Vec<(EntityId, EntityId)> = moved
+            .iter()
+            .filter_map(|child| Some((*child, self.parent(child)?)))
+            .collect();
+
+        let mut split = self.empty_clone();
+        split.allocator = self.allocator.clone_allocator();
+        split.reserved_blocks = self.reserved_blocks.clone();
+        for entity_id in moved {
+            let order = self
+                .component_order
+                .get(&entity_id)
+                .cloned()
+                .unwrap_or_default();
+            let Some(components) = self.entity_components.remove(&entity_id) else {
+                continue;
+            };
+            let creation = self.creation_order.get(&entity_id).copied();
+            let reserved = self.reserved.contains(&entity_id);
+            self.entity_removed(entity_id, &components);
+            split.entity_components.insert(entity_id, components);
+            split
+                .creation_order
+                .extend(creation.map(|creation| (entity_id, creation)));
+            if reserved {
+                split.reserved.insert(entity_id);
+            }
+            for type_id in order {
+                split.component_added(entity_id, type_id);
+            }
+        }
+        split.next_creation = self.next_creation;
+        for (child, parent) in parents {
+            // Only fails when the parent stayed behind
+            let _ = split.set_parent(child, parent);
+        }
+        split
+    }
+
+    // Comparing
+
+    ///
+    /// Registers a component type as comparable, so that `diff` can tell
+    /// whether its value changed
+    /// ```
+    /// use enco::world::*;
+    /// #[derive(PartialEq)]
+    /// struct SomeComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.register_comparable::<SomeComponent>();
+    /// ```
+    pub fn register_comparable<T: Any + PartialEq>(&mut self) {
+        self.eq_fns.insert(TypeId::of::<T>(), eq_boxed::<T>);
+    }
+
+    ///
+    /// Computes what changed between a previous state of the world, usually
+    /// a snapshot, and the current one. An entity's component counts as
+    /// changed if it was added or removed, or if its type is registered as
+    /// comparable and its value differs. When either world is a snapshot,
+    /// types the snapshot couldn't copy are left out of the comparison.
+    /// ```
+    /// use enco::world::*;
+    /// #[derive(Clone, PartialEq)]
+    /// struct SomeComponent(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.register_cloneable::<SomeComponent>();
+    /// world.register_comparable::<SomeComponent>();
+    /// let entity_id = world.create_entity().with(SomeComponent(1)).done();
+    ///
+    /// let previous = world.snapshot();
+    /// world.get_entity_component_mut::<SomeComponent>(&entity_id).unwrap().0 = 2;
+    ///
+    /// let diff = world.diff(&previous);
+    /// assert!(diff.changed.contains_key(&entity_id));
+    /// ```
+    pub fn diff(&self, previous: &World) -> WorldDiff {
+        let mut diff = WorldDiff::default();
+        for (entity_id, components) in &self.entity_components {
+            let Some(previous_components) = previous.entity_components.get(entity_id) else {
+                diff.added.insert(*entity_id);
+                continue;
+            };
+            let changed = self.changed_components(previous, previous_components, components);
+            if !changed.is_empty() {
+                diff.changed.insert(*entity_id, changed);
+            }
+        }
+        for entity_id in previous.entity_components.keys() {
+            if !self.entity_components.contains_key(entity_id) {
+                diff.removed.insert(*entity_id);
+            }
+        }
+        diff
+    }
+
+    ///
+    /// Like `diff`, but only looks at one entity, telling its added,
+    /// removed and changed component types apart. If the entity only exists
+    /// in one of the worlds, all of its components count as added or
+    /// removed.
+    /// ```
+    /// use std::any::TypeId;
+    /// use enco::world::*;
+    /// #[derive(Clone, PartialEq)]
+    /// struct Health(i32);
+    ///
+    /// let mut world = World::new();
+    /// world.register_cloneable::<Health>();
+    /// world.register_comparable::<Health>();
+    /// let entity_id = world.create_entity().with(Health(10)).done();
+    ///
+    /// let previous = world.snapshot();
+    /// world.get_entity_component_mut::<Health>(&entity_id).unwrap().0 = 5;
+    ///
+    /// let diff = world.entity_diff(&previous, &entity_id);
+    /// assert_eq!(diff.changed, vec![TypeId::of::<Health>()]);
+    /// ```
+    pub fn entity_diff(&self, other: &World, entity_id: &EntityId) -> EntityDiff {
+        let empty = ComponentHash::new();
+        let before = other.entity_components.get(entity_id).unwrap_or(&empty);
+        let after = self.entity_components.get(entity_id).unwrap_or(&empty);
+        let mut diff = EntityDiff::default();
+        for type_id in self.changed_components(other, before, after) {
+            if !before.contains_key(&type_id) {
+                diff.added.push(type_id);
+            } else if !after.contains_key(&type_id) {
+                diff.removed.push(type_id);
+            } else {
+                diff.changed.push(type_id);
+            }
+        }
+        diff
+    }
+
+    ///
+    /// Checks whether two worlds hold the same entities with the same
+    /// component types. Components registered as comparable in this world
+    /// are compared by value, the others only by presence. As in `diff`,
+    /// types a snapshot couldn't copy are ignored. The allocator state is
+    /// ignored too, so the worlds may hand out different ids next.
+    /// ```
+    /// use enco::world::*;
+    /// #[derive(PartialEq)]
+    /// struct SomeComponent(i32);
+    ///
+    /// let mut world_1 = World::new();
+    /// let mut world_2 = World::new();
+    /// world_1.register_comparable::<SomeComponent>();
+    /// world_1.create_entity().with(SomeComponent(1)).done();
+    /// world_2.create_entity().with(SomeComponent(1)).done();
+    ///
+    /// assert!(world_1.equivalent_to(&world_2));
+    /// ```
+    pub fn equivalent_to(&self, other: &World) -> bool {
+        self.entity_components.len() == other.entity_components.len()
+            && self
+                .entity_components
+                .iter()
+                .all(|(entity_id, components)| {
+                    other
+                        .entity_components
+                        .get(entity_id)
+                        .is_some_and(|other_components| {
+                            self.changed_components(other, other_components, components)
+                                .is_empty()
+                        })
+                })
+    }
+
+    fn changed_components(
+        &self,
+        other: &World,
+        before: &ComponentHash,
+        after: &ComponentHash,
+    ) -> Vec<TypeId> {
+        // A snapshot only holds the types that were cloneable when it was
+        // taken, so the others would look removed or added
+        let captured = [other, self]
+            .into_iter()
+            .find(|world| world.is_snapshot)
+            .map(|snapshot| &snapshot.clone_fns);
+        let compared = |type_id: &TypeId| captured.is_none_or(|types| types.contains_key(type_id));
+        let mut changed: Vec<TypeId> = after
+            .iter()
+            .filter(|(type_id, _component)| compared(type_id))
+            .filter(|(type_id, component)| match before.get(*type_id) {
+                None => true,
+                Some(previous) => self
+                    .eq_fns
+                    .get(*type_id)
+                    .is_some_and(|eq_fn| !eq_fn(previous.as_ref(), component.as_ref())),
+            })
+            .map(|(type_id, _component)| *type_id)
+            .chain(
+                before
+                    .keys()
+                    .filter(|type_id| compared(type_id) && !after.contains_key(*type_id))
+                    .copied(),
+            )
+            .collect();
+        changed.sort_unstable();
+        changed
+    }
+
+    fn empty_clone(&self) -> World {
+        let mut world = World::new();
+        world.clone_fns = self.clone_fns.clone();
+        world.eq_fns = self.eq_fns.clone();
+        world.component_names = self.component_names.clone();
+        world.component_sizes = self.component_sizes.clone();
+        world.components_per_entity = self.components_per_entity;
+        world
+    }
+
+    fn clone_entity_into(&self, target: &mut World, entity_id: &EntityId, target_id: EntityId) {
+        let mut components = ComponentHash::new();
+        let mut type_ids = Vec::new();
+        if let Some(source) = self.entity_components.get(entity_id) {
+            for type_id in self.component_order.get(entity_id).into_iter().flatten() {
+                if let (Some(component), Some(clone_fn)) =
+                    (source.get(type_id), self.clone_fns.get(type_id))
+                {
+                    components.insert(*type_id, clone_fn(component.as_ref()));
+                    type_ids.push(*type_id);
+                }
+            }
+        }
+        target.entity_components.insert(target_id, components);
+        target.stamp_creation(target_id);
+        for type_id in type_ids {
+            target.component_added(target_id, type_id);
+        }
+    }
+
+    // Bookkeeping
+
+    fn in_reserved_block(&self, entity_id: &EntityId) -> bool {
+        self.reserved_blocks
+            .iter()
+            .any(|block| block.contains(entity_id))
+    }
+
+    /// Lets the allocator start over from `next_id`, or from the end of the
+    /// last reserved block if that is higher, so reserved ids stay out of
+    /// its reach
+    fn reset_allocator(&mut self, next_id: EntityId) {
+        let next_id = self
+            .reserved_blocks
+            .iter()
+            .map(|block| block.end)
+            .fold(next_id, EntityId::max);
+        self.allocator.reset(next_id);
+    }
+
+    /// Panics in debug builds if an iterator over the world is still counted
+    /// as alive, which can only happen if one was leaked
+    fn check_not_iterating(&self) {
+        debug_assert!(
+            self.active_borrows.get() == 0,
+            "the world was changed while {} iterator(s) over it were alive",
+            self.active_borrows.get()
+        );
+    }
+
+    /// Asks the allocator for ids until it returns one that isn't taken, so
+    /// a misbehaving allocator can't overwrite an existing entity
+    fn allocate_vacant_id(&mut self) -> EntityId {
+        loop {
+            let entity_id = self.allocator.allocate();
+            if !self.entity_components.contains_key(&entity_id) {
+                return entity_id;
+            }
+        }
+    }
+
+    fn entity_spawned(&mut self, entity_id: EntityId) {
+        self.entity_components.insert(
+            entity_id,
+            HashMap::with_capacity(self.components_per_entity),
+        );
+        self.stamp_creation(entity_id);
+        self.record(WorldOp::Spawn(entity_id));
+    }
+
+    fn stamp_creation(&mut self, entity_id: EntityId) {
+        self.creation_order.insert(entity_id, self.next_creation);
+        self.next_creation += 1;
+    }
+
+    fn put_component<T: Any>(&mut self, entity_id: EntityId, component: T) {
+        if let Some(components) = self.entity_components.get_mut(&entity_id) {
+            let boxed = pooled_box(&mut self.component_pools, component);
+            components.insert(TypeId::of::<T>(), boxed);
+            self.component_added(entity_id, TypeId::of::<T>());
+        }
+    }
+
+    /// Hands a removed component's box to its type's pool, if there is one
+    fn recycle(&mut self, type_id: TypeId, component: Box<dyn Any>) {
+        if let Some(pool) = self.component_pools.get_mut(&type_id) {
+            pool.stash(component);
+        }
+    }
+
+    fn take_component<T: Any>(&mut self, entity_id: &EntityId) -> Option<T> {
+        let type_id = TypeId::of::<T>();
+        let component = self
+            .entity_components
+            .get_mut(entity_id)?
+            .remove(&type_id)?;
+        self.component_removed(*entity_id, type_id);
+        component.downcast::<T>().ok().map(|component| *component)
+    }
+
+    fn cached_query(&self, type_id: TypeId) -> Rc<[EntityId]> {
+        let mut cache = self.query_cache.borrow_mut();
+        let entity_ids = cache.entry(type_id).or_insert_with(|| {
+            let mut entity_ids: Vec<EntityId> = self
+                .component_index
+                .get(&type_id)
+                .into_iter()
+                .flatten()
+                .copied()
+                .collect();
+            entity_ids.sort_unstable();
+            entity_ids.into()
+        });
+        Rc::clone(entity_ids)
+    }
+
+    /// Counts the iterator as an active borrow until it is dropped
+    fn track<I: Iterator>(&self, iterator: I) -> Tracked<'_, I> {
+        self.active_borrows.set(self.active_borrows.get() + 1);
+        Tracked {
+            iterator,
+            active_borrows: &self.active_borrows,
+        }
+    }
+
+    /// Every structural change goes through here
+    fn record(&mut self, op: WorldOp) {
+        self.structural_generation += 1;
+        if let Some(change_log) = &mut self.change_log {
+            change_log.push(op);
+        }
+    }
+
+    /// Purges a deleted entity from every auxiliary structure. Anything
+    /// keyed by entity id must be cleaned up here.
+    fn entity_removed(&mut self, entity_id: EntityId, components: &ComponentHash) {
+        // A despawn is logged as a single op, not as one removal per component
+        let change_log = self.change_log.take();
+        for type_id in components.keys() {
+            self.component_removed(entity_id, *type_id);
+        }
+        self.change_log = change_log;
+        self.record(WorldOp::Despawn(entity_id));
+        self.component_order.remove(&entity_id);
+        self.creation_order.remove(&entity_id);
+        self.reserved.remove(&entity_id);
+        for tag_id in self.entity_tags.remove(&entity_id).into_iter().flatten() {
+            if let Some(entities) = self.tagged.get_mut(&tag_id) {
+                entities.remove(&entity_id);
+            }
+        }
+        self.detach_from_parent(&entity_id);
+        if let Some(children) = self.children.remove(&entity_id) {
+            for child in children {
+                self.parents.remove(&child);
+            }
+        }
+        for added in self.scopes.values_mut() {
+            added.retain(|(scoped_id, _type_id)| *scoped_id != entity_id);
+        }
+        if !self.in_reserved_block(&entity_id) {
+            self.allocator.free(entity_id);
+        }
+        if self.entity_components.is_empty() {
+            if let Some(on_empty) = &mut self.on_empty {
+                on_empty();
+            }
+        }
+    }
+
+    fn component_added(&mut self, entity_id: EntityId, type_id: TypeId) {
+        self.query_cache.get_mut().remove(&type_id);
+        self.reserved.remove(&entity_id);
+        self.component_index
+            .entry(type_id)
+            .or_default()
+            .insert(entity_id);
+        if let Some(entities) = self.added_components.get_mut(&type_id) {
+            entities.insert(entity_id);
+        }
+        let order = self.component_order.entry(entity_id).or_default();
+        if !order.contains(&type_id) {
+            order.push(type_id);
+        }
+        self.record(WorldOp::Add(entity_id, type_id));
+        match &mut self.batched_types {
+            Some(batched_types) => {
+                batched_types.insert(type_id);
+            }
+            None => fire_hooks(&self.on_add_hooks, &type_id, entity_id),
+        }
+    }
+
+    fn component_removed(&mut self, entity_id: EntityId, type_id: TypeId) {
+        self.query_cache.get_mut().remove(&type_id);
+        if let Some(entities) = self.component_index.get_mut(&type_id) {
+            entities.remove(&entity_id);
+        }
+        if let Some(entities) = self.added_components.get_mut(&type_id) {
+            entities.remove(&entity_id);
+        }
+        if let Some(order) = self.component_order.get_mut(&entity_id) {
+            order.retain(|ty| *ty != type_id);
+        }
+        self.record(WorldOp::Remove(entity_id, type_id));
+        match &mut self.batched_types {
+            Some(batched_types) => {
+                batched_types.insert(type_id);
+            }
+            None => fire_hooks(&self.on_remove_hooks, &type_id, entity_id),
+        }
+    }
+
+    /// Picks the smallest presence set among the given types, so joins
+    /// only have to look at the entities that could possibly match
+    fn smallest_index(&self, type_ids: &[TypeId]) -> impl Iterator<Item = EntityId> + '_ {
+        let candidates = type_ids
+            .iter()
+            .map(|type_id| self.component_index.get(type_id))
+            .min_by_key(|entities| entities.map_or(0, HashSet::len))
+            .flatten()
+            .into_iter()
+            .flatten()
+            .copied();
+        // The index is a hash set, so it has to be sorted to keep the
+        // feature's promise
+        #[cfg(not(feature = "deterministic"))]
+        return candidates;
+        #[cfg(feature = "deterministic")]
+        {
+            let mut candidates: Vec<EntityId> = candidates.collect();
+            candidates.sort_unstable();
+            candidates.into_iter()
+        }
+    }
+
+    // Iterators
+
+    ///
+    /// Returns an iterator that goes through every entity id
+    /// ```
+    /// use enco::world::*;
+    ///
+    /// let mut world = World::new();
+    ///
+    /// world.create_entity().done();
+    /// world.create_entity().done();
+    /// for entity_id in world.iter() {
+    ///     // Do something here
+    /// }
+    /// ```
+    pub fn iter(&self) -> impl Iterator<Item = &usize> {
+        self.track(self.entity_components.keys())
+    }
+
+    ///
+    /// Returns how many iterators returned by `iter`, `query`, `query2`,
+    /// `query3`, `query_counted` and `join` are still alive. The borrow checker already
+    /// keeps the world from being changed while one is; this is meant for
+    /// tests and debugging, to check that none was leaked. In debug builds,
+    /// `create_entity`, `delete_entity` and `add_component` panic while
+    /// this isn't 0.
+    /// ```
+    /// use enco::world::*;
+    /// struct Health(i32);
+    ///
+    /// let world = World::new();
+    /// let healths = world.query::<Health>();
+    /// assert_eq!(world.active_borrows(), 1);
+    ///
+    /// drop(healths);
+    /// assert_eq!(world.active_borrows(), 0);
+    /// ```
+    pub fn active_borrows(&self) -> usize {
+        self.active_borrows.get()
     }
 
     ///
-    /// Creates an entity in the current world.
+    /// Returns the ids of every entity, sorted. Since the vector doesn't
+    /// borrow the world, it can be used to modify entities while looping.
     /// ```
     /// use enco::world::*;
-    /// struct MyComponent(i32);
     ///
     /// let mut world = World::new();
-    /// let entity_id = world
-    ///          .create_entity()
-    ///          .with(MyComponent(12))
-    ///          .done();
+    ///
+    /// world.create_entity().done();
+    /// world.create_entity().done();
+    /// for entity_id in world.entity_ids() {
+    ///     world.delete_entity(&entity_id).unwrap();
+    /// }
     /// ```
-    pub fn create_entity(&mut self) -> &mut Self {
-        self.entity_components
-            .insert(self.new_entity_id, HashMap::new());
-        self
+    pub fn entity_ids(&self) -> Vec<EntityId> {
+        let mut entity_ids: Vec<EntityId> = self.entity_components.keys().copied().collect();
+        entity_ids.sort_unstable();
+        entity_ids
     }
 
-    pub fn with<T: Any>(&mut self, component: T) -> &mut Self {
-        self.entity_components
-            .get_mut(&self.new_entity_id)
-            .unwrap()
-            .insert(TypeId::of::<T>(), Box::<T>::new(component));
-        self
+    ///
+    /// Returns an iterator over every entity along with its components,
+    /// borrowed mutably. Meant for changing component values: adding or
+    /// removing entries through it bypasses the index and the hooks, so use
+    /// `add_component` and `delete_component` for that.
+    /// ```
+    /// use std::any::TypeId;
+    /// use enco::world::*;
+    /// struct Health(i32);
+    ///
+    /// let mut world = World::new();
+    /// let entity_id = world.create_entity().with(Health(10)).done();
+    ///
+    /// for (_entity_id, components) in world.entities_mut() {
+    ///     if let Some(health) = components.get_mut(&TypeId::of::<Health>()) {
+    ///         health.downcast_mut::<Health>().unwrap().0 = 0;
+    ///     }
+    /// }
+    /// assert_eq!(world.get_entity_component::<Health>(&entity_id).unwrap().0, 0);
+    /// ```
+    pub fn entities_mut(&mut self) -> impl Iterator<Item = (&EntityId, &mut ComponentHash)> {
+        self.entity_components.iter_mut()
     }
+}
 
-    pub fn done(&mut self) -> EntityId {
-        self.new_entity_id += 1;
-        self.new_entity_id - 1
+///
+/// The differences between two states of a world, as computed by
+/// `World::diff`
+#[derive(Debug, Default, PartialEq)]
+pub struct WorldDiff {
+    pub added: HashSet<EntityId>,
+    pub removed: HashSet<EntityId>,
+    pub changed: HashMap<EntityId, Vec<TypeId>>,
+}
+
+///
+/// The differences between two states of one entity, as computed by
+/// `World::entity_diff`. Each list is sorted.
+#[derive(Debug, Default, PartialEq)]
+pub struct EntityDiff {
+    pub added: Vec<TypeId>,
+    pub removed: Vec<TypeId>,
+    pub changed: Vec<TypeId>,
+}
+
+///
+/// A copy of the cloneable resources of a world, as taken by
+/// `World::snapshot_resources`
+pub struct ResourceSnapshot {
+    resources: HashMap<TypeId, (Box<dyn Any>, CloneFn)>,
+}
+
+impl ResourceSnapshot {
+    pub fn len(&self) -> usize {
+        self.resources.len()
     }
 
-    pub fn num_entities(&self) -> usize {
-        self.entity_components.len()
+    pub fn is_empty(&self) -> bool {
+        self.resources.is_empty()
     }
 
-    pub fn num_components(&self, entity_id: &EntityId) -> Result<usize, WorldError> {
-        if let Some(components) = self.entity_components.get(&entity_id) {
-            return Ok(components.len());
+    pub fn contains<R: Any>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<R>())
+    }
+}
+
+///
+/// A structural change to a world, as recorded by the change log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorldOp {
+    Spawn(EntityId),
+    Despawn(EntityId),
+    Add(EntityId, TypeId),
+    Remove(EntityId, TypeId),
+}
+
+struct ComponentPool {
+    capacity: usize,
+    reset: ResetFn,
+    boxes: Vec<Box<dyn Any>>,
+}
+
+impl ComponentPool {
+    fn stash(&mut self, mut component: Box<dyn Any>) {
+        if self.boxes.len() < self.capacity {
+            (self.reset)(component.as_mut());
+            self.boxes.push(component);
         }
-        Err(WorldError::EntityDoesNotExist)
     }
+}
 
-    pub fn query<T: Any>(&self) -> impl Iterator<Item = &T> {
-        let query = self
-            .entity_components
-            .iter()
-            .filter_map(|(entity_id, _components)| {
-                let component_option = self.get_entity_component::<T>(entity_id);
-                component_option
-            });
-        query
+struct Tracked<'a, I> {
+    iterator: I,
+    active_borrows: &'a Cell<usize>,
+}
+
+impl<I: Iterator> Iterator for Tracked<'_, I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        self.iterator.next()
     }
 
-    pub fn query_mut<T: Any>(&mut self) -> impl Iterator<Item = &mut T> {
-        let self_ptr = self as *mut Self;
-        let query =
-            self.entity_components
-                .iter_mut()
-                .filter_map(move |(entity_id, _components)| unsafe {
-                    let component_option = (*self_ptr).get_entity_component_mut::<T>(entity_id);
-                    component_option
-                });
-        query
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iterator.size_hint()
+    }
+}
+
+impl<I> Drop for Tracked<'_, I> {
+    fn drop(&mut self) {
+        self.active_borrows.set(self.active_borrows.get() - 1);
+    }
+}
+
+/// Ends a batch even if the closure running in it panics, so that hooks
+/// aren't left suppressed
+struct BatchGuard<'a>(&'a mut World);
+
+impl Drop for BatchGuard<'_> {
+    fn drop(&mut self) {
+        self.0.batched_types = None;
+    }
+}
+
+struct InternPool {
+    values: Box<dyn Any>,
+    prune: PruneFn,
+}
+
+///
+/// A component shared between entities, as added by
+/// `World::add_interned`
+pub struct Interned<T>(Rc<T>);
+
+impl<T> Interned<T> {
+    ///
+    /// Whether both components point to the same stored value
+    pub fn ptr_eq(a: &Self, b: &Self) -> bool {
+        Rc::ptr_eq(&a.0, &b.0)
+    }
+}
+
+impl<T> Clone for Interned<T> {
+    fn clone(&self) -> Self {
+        Interned(Rc::clone(&self.0))
+    }
+}
+
+impl<T> Deref for Interned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
     }
+}
+
+///
+/// Builds an entity created by `World::create_entity`. The entity already
+/// exists in the world; the builder only adds components to it.
+pub struct EntityBuilder<'a> {
+    world: &'a mut World,
+    entity_id: EntityId,
+    error: Option<WorldError>,
+}
+
+///
+/// The component of a given type on one entity, which may or may not be
+/// there yet, as returned by `World::component_entry`
+pub struct ComponentEntry<'a, T: Any> {
+    world: &'a mut World,
+    entity_id: EntityId,
+    component: PhantomData<T>,
+}
+
+///
+/// Decides what happens when an `EntityBuilder` is misused, like adding
+/// two components of the same type to one entity
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StrictMode {
+    /// Panic right away
+    #[default]
+    Strict,
+    /// Keep the first component and report the error from `try_done`
+    Lenient,
+}
 
+impl EntityBuilder<'_> {
     ///
-    /// Deletes an entity from the world
+    /// Adds a component to the entity being built. Adding a second component
+    /// of the same type is a mistake, handled according to the world's
+    /// `StrictMode`.
+    pub fn with<T: Any>(&mut self, component: T) -> &mut Self {
+        if !self.world.has_component::<T>(&self.entity_id) {
+            self.world.put_component(self.entity_id, component);
+            return self;
+        }
+        match self.world.strict_mode {
+            StrictMode::Strict => panic!(
+                "entity {} already has a `{}` component",
+                self.entity_id,
+                std::any::type_name::<T>()
+            ),
+            StrictMode::Lenient => {
+                self.error
+                    .get_or_insert(WorldError::EntityAlreadyHasComponent(TypeId::of::<T>()));
+            }
+        }
+        self
+    }
+
     ///
+    /// Adds the default value of a component to the entity being built
     /// ```
     /// use enco::world::*;
+    /// #[derive(Default)]
+    /// struct Selected;
     ///
     /// let mut world = World::new();
-    /// let entity_id = world
-    ///          .create_entity()
-    ///          .done();
-    /// world.delete_entity(&entity_id).unwrap();
+    /// let entity_id = world.create_entity().with_default::<Selected>().done();
+    /// assert!(world.has_component::<Selected>(&entity_id));
     /// ```
-    ///
-    pub fn delete_entity(&mut self, entity_id: &EntityId) -> Result<(), WorldError> {
-        if let Some(_) = self.entity_components.remove(&entity_id) {
-            return Ok(());
-        }
-        Err(WorldError::DeleteEntity)
+    pub fn with_default<T: Any + Default>(&mut self) -> &mut Self {
+        self.with(T::default())
     }
 
-    /// Adds a component to an entity
     ///
+    /// Adds a component to the entity being built only if `condition`
+    /// holds, without breaking the chain
     /// ```
     /// use enco::world::*;
-    /// struct SomeComponent(i32);
+    /// struct Player;
     ///
     /// let mut world = World::new();
-    /// let entity_id = world
-    ///          .create_entity()
-    ///          .done();
-    /// world.add_component(&entity_id, SomeComponent(1)).unwrap();
-    /// ```
+    /// let is_player = false;
+    /// let entity_id = world.create_entity().with_if(is_player, Player).done();
     ///
-    pub fn add_component<T: Any>(
-        &mut self,
-        entity_id: &EntityId,
-        component: T,
-    ) -> Result<(), WorldError> {
-        let type_id = TypeId::of::<T>();
-        if let Some(components_hash) = self.entity_components.get_mut(entity_id) {
-            if components_hash.contains_key(&type_id) {
-                return Err(WorldError::EntityAlreadyHasComponent(type_id));
-            }
-            components_hash.insert(type_id, Box::new(component));
-            return Ok(());
+    /// assert!(!world.has_component::<Player>(&entity_id));
+    /// ```
+    pub fn with_if<T: Any>(&mut self, condition: bool, component: T) -> &mut Self {
+        if condition {
+            self.with(component);
         }
-        Err(WorldError::EntityAlreadyHasComponent(type_id))
+        self
     }
 
     ///
-    /// Deletes a component from an entity
+    /// Finishes building, returning the id of the new entity. The entity
+    /// is created along with the builder, so the id always refers to an
+    /// entity that exists, even if `done` is called more than once. Mistakes
+    /// recorded by a lenient world are dropped; use `try_done` to get them.
+    pub fn done(&mut self) -> EntityId {
+        self.entity_id
+    }
+
     ///
+    /// Like `done`, but returns the first mistake made while building, if
+    /// any. Only lenient worlds get here with a mistake; strict ones panic
+    /// as soon as it happens.
     /// ```
     /// use enco::world::*;
-    /// struct SomeComponent(i32);
-    /// struct SomeOtherComponent(i32);
+    /// struct Health(i32);
     ///
     /// let mut world = World::new();
-    /// let entity_id = world
-    ///          .create_entity()
-    ///          .with(SomeComponent(12))
-    ///          .with(SomeOtherComponent(12))
-    ///          .done();
-    /// world.delete_component::<SomeComponent>(&entity_id).unwrap();
-    /// ```
+    /// world.set_strict_mode(StrictMode::Lenient);
     ///
-    pub fn delete_component<T: Any>(&mut self, entity_id: &EntityId) -> Result<(), WorldError> {
-        if let Some(entity_components) = self.entity_components.get_mut(entity_id) {
-            if let Some(_) = entity_components.remove(&TypeId::of::<T>()) {
-                return Ok(());
-            }
-            return Err(WorldError::EntityDoesNotHaveComponent(TypeId::of::<T>()));
+    /// let result = world.create_entity().with(Health(1)).with(Health(2)).try_done();
+    /// assert!(result.is_err());
+    /// ```
+    pub fn try_done(&mut self) -> Result<EntityId, WorldError> {
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => Ok(self.entity_id),
         }
-        Err(WorldError::DeleteEntity)
     }
+}
 
-    pub fn get_entity_component<T: Any>(&self, entity_id: &EntityId) -> Option<&T> {
-        let components = self.entity_components.get(&entity_id)?;
-        components.get(&TypeId::of::<T>())?.downcast_ref::<T>()
+impl<'a, T: Any> ComponentEntry<'a, T> {
+    ///
+    /// Whether the entity has the component
+    pub fn is_occupied(&self) -> bool {
+        self.world.has_component::<T>(&self.entity_id)
     }
 
-    pub fn get_entity_component_mut<T: Any>(&mut self, entity_id: &EntityId) -> Option<&mut T> {
-        let components = self.entity_components.get_mut(&entity_id)?;
-        components.get_mut(&TypeId::of::<T>())?.downcast_mut::<T>()
+    ///
+    /// Adds `component` unless the entity already has one, then mutably
+    /// borrows the entity's component
+    pub fn or_insert(self, component: T) -> &'a mut T {
+        self.or_insert_with(|| component)
     }
 
-    // Iterators
-
     ///
-    /// Returns an iterator that goes through every entity id
-    /// ```
-    /// use enco::world::*;
+    /// Like `or_insert`, but only builds the component when it is needed
+    pub fn or_insert_with<F: FnOnce() -> T>(self, f: F) -> &'a mut T {
+        if !self.is_occupied() {
+            self.world.put_component(self.entity_id, f());
+        }
+        // The entity exists, so the component is there now
+        self.world
+            .get_entity_component_mut::<T>(&self.entity_id)
+            .unwrap()
+    }
+
     ///
-    /// let mut world = World::new();
+    /// Modifies the component in place if the entity has one
+    pub fn and_modify<F: FnOnce(&mut T)>(self, f: F) -> Self {
+        if let Some(component) = self.world.get_entity_component_mut::<T>(&self.entity_id) {
+            f(component);
+        }
+        self
+    }
+}
+
+impl<'a, T: Any + Default> ComponentEntry<'a, T> {
     ///
-    /// world.create_entity().done();
-    /// world.create_entity().done();
-    /// for entity_id in world.iter() {
-    ///     // Do something here
-    /// }
-    /// ```
-    pub fn iter(&self) -> impl Iterator<Item = &usize> {
-        self.entity_components.iter().map(|entry| entry.0)
+    /// Adds the component's default value unless the entity already has
+    /// one, then mutably borrows the entity's component
+    pub fn or_default(self) -> &'a mut T {
+        self.or_insert_with(T::default)
+    }
+}
+
+///
+/// Returns an iterator over the components of the given type in every one
+/// of the worlds, each tagged with the index of its world and its entity
+/// id. Within a world, entities come in ascending id order.
+/// ```
+/// use enco::world::*;
+/// struct Health(i32);
+///
+/// let mut left = World::new();
+/// let mut right = World::new();
+/// left.create_entity().with(Health(1)).done();
+/// let entity_id = right.create_entity().with(Health(2)).done();
+///
+/// let healths: Vec<(usize, EntityId, i32)> = query_all::<Health>(&[&left, &right])
+///     .map(|(index, entity_id, health)| (index, entity_id, health.0))
+///     .collect();
+/// assert_eq!(healths[1], (1, entity_id, 2));
+/// ```
+pub fn query_all<'a, T: Any>(
+    worlds: &'a [&'a World],
+) -> impl Iterator<Item = (usize, EntityId, &'a T)> {
+    worlds.iter().enumerate().flat_map(|(index, world)| {
+        let entity_ids = world.cached_query(TypeId::of::<T>());
+        (0..entity_ids.len()).filter_map(move |i| {
+            let entity_id = entity_ids[i];
+            Some((
+                index,
+                entity_id,
+                world.get_entity_component::<T>(&entity_id)?,
+            ))
+        })
+    })
+}
+
+fn fire_hooks(hooks: &HookHash, type_id: &TypeId, entity_id: EntityId) {
+    if let Some(hooks) = hooks.get(type_id) {
+        for hook in hooks {
+            hook(entity_id);
+        }
+    }
+}
+
+fn reset_boxed<T: Any + Default>(component: &mut dyn Any) {
+    if let Some(component) = component.downcast_mut::<T>() {
+        *component = T::default();
+    }
+}
+
+fn pooled_box<T: Any>(pools: &mut HashMap<TypeId, ComponentPool>, component: T) -> Box<dyn Any> {
+    let pooled = pools
+        .get_mut(&TypeId::of::<T>())
+        .and_then(|pool| pool.boxes.pop())
+        .and_then(|boxed| boxed.downcast::<T>().ok());
+    match pooled {
+        Some(mut boxed) => {
+            *boxed = component;
+            boxed
+        }
+        None => Box::new(component),
+    }
+}
+
+/// Drops the interned values that only the pool still holds on to
+fn prune_interned<T: Any + Eq + Hash>(values: &mut dyn Any) {
+    if let Some(values) = values.downcast_mut::<HashSet<Rc<T>>>() {
+        values.retain(|value| Rc::strong_count(value) > 1);
+    }
+}
+
+fn clone_boxed<T: Any + Clone>(component: &dyn Any) -> Box<dyn Any> {
+    Box::new(component.downcast_ref::<T>().unwrap().clone())
+}
+
+fn eq_boxed<T: Any + PartialEq>(a: &dyn Any, b: &dyn Any) -> bool {
+    match (a.downcast_ref::<T>(), b.downcast_ref::<T>()) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn debug_assert_distinct<A: Any, B: Any>() {
+    debug_assert_ne!(
+        TypeId::of::<A>(),
+        TypeId::of::<B>(),
+        "cannot borrow `{}` mutably twice",
+        std::any::type_name::<A>()
+    );
+}
+
+fn two_components_mut<A: Any, B: Any>(components: &mut ComponentHash) -> Option<(&mut A, &mut B)> {
+    let type_a = TypeId::of::<A>();
+    let type_b = TypeId::of::<B>();
+    if type_a == type_b {
+        return None;
     }
+    let [a, b] = components.get_disjoint_mut([&type_a, &type_b]);
+    Some((a?.downcast_mut::<A>()?, b?.downcast_mut::<B>()?))
 }
 
 #[cfg(test)]
@@ -229,6 +4780,70 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    pub fn delete_entity_purges_auxiliary_structures() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let parent = world.create_entity().done();
+        let entity_id = world.create_entity().with(NodeDrawingComponent(1)).done();
+        let child = world.create_entity().done();
+        world.set_parent(entity_id, parent)?;
+        world.set_parent(child, entity_id)?;
+
+        world.delete_entity(&entity_id)?;
+
+        assert!(!world.parents.contains_key(&entity_id));
+        assert!(!world.parents.contains_key(&child));
+        assert!(!world.children.contains_key(&entity_id));
+        assert!(!world.children.contains_key(&parent));
+        assert!(world
+            .component_index
+            .values()
+            .all(|entities| !entities.contains(&entity_id)));
+        assert!(!world.creation_order.contains_key(&entity_id));
+        assert!(!world.reserved.contains(&entity_id));
+        Ok(())
+    }
+
+    #[test]
+    pub fn validate_flags_dangling_links() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let parent = world.create_entity().done();
+        let child = world.create_entity().with(NodeDrawingComponent(1)).done();
+        world.set_parent(child, parent)?;
+        assert!(world.validate().is_empty());
+
+        world.entity_components.remove(&parent);
+        let problems = world.validate();
+        assert_eq!(problems.len(), 3);
+        assert!(problems
+            .iter()
+            .any(|problem| problem.to_string().contains("linked to parent 0")));
+
+        world.entity_components.remove(&child);
+        assert_eq!(world.validate().len(), 6);
+        Ok(())
+    }
+
+    #[test]
+    pub fn reindex_drops_dangling_links() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let parent = world.create_entity().done();
+        let child = world.create_entity().with(NodeDrawingComponent(1)).done();
+        let survivor = world.create_entity().with(NodeDrawingComponent(2)).done();
+        world.set_parent(child, parent)?;
+        world.add_tag(child, "selected")?;
+
+        world.entity_components.remove(&parent);
+        world.entity_components.remove(&child);
+        let remap = world.reindex();
+
+        assert_eq!(remap, [(survivor, 0)].into_iter().collect());
+        assert!(world.validate().is_empty());
+        assert_eq!(world.count::<NodeDrawingComponent>(), 1);
+        Ok(())
+    }
+
     #[test]
     pub fn world_capacity() -> Result<(), WorldError> {
         let mut world = World::new();
@@ -253,5 +4868,52 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    pub fn world_with_capacity_for() -> Result<(), WorldError> {
+        // Allocation counts would be compared in a benchmark; this only
+        // checks that presizing takes effect and changes no behavior
+        let mut world = World::with_capacity_for(64, 3);
+        let mut entity_ids = vec![];
+        for i in 0..64 {
+            let entity_id = world.create_entity().with(NodeDrawingComponent(i)).done();
+            assert!(world.entity_components[&entity_id].capacity() >= 3);
+            entity_ids.push(entity_id);
+        }
+        world.delete_entity(&entity_ids[10])?;
+
+        assert_eq!(world.num_entities(), 63);
+        assert_eq!(world.count::<NodeDrawingComponent>(), 63);
+        assert_eq!(
+            world
+                .get_entity_component::<NodeDrawingComponent>(&entity_ids[20])
+                .unwrap()
+                .0,
+            20
+        );
+        Ok(())
+    }
+
+    #[test]
+    pub fn world_len_and_capacity() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        assert!(world.is_empty());
+        assert_eq!(world.len(), 0);
+
+        world.create_entity().with(NodeDrawingComponent(1)).done();
+        world.create_entity().done();
+
+        assert!(!world.is_empty());
+        assert_eq!(world.len(), world.num_entities());
+        assert_eq!(world.len(), 2);
+
+        world.reserve_entities(100);
+        assert!(world.capacity() >= world.len());
+        #[cfg(not(feature = "deterministic"))]
+        assert!(world.capacity() >= world.len() + 100);
+
+        Ok(())
+    }
+
     struct NodeDrawingComponent(i32);
 }
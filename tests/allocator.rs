@@ -0,0 +1,311 @@
+#[cfg(test)]
+mod tests {
+
+    use enco::allocator::*;
+    use enco::custom_errors::*;
+    use enco::world::*;
+
+    fn spawn_delete_spawn(world: &mut World) -> Result<Vec<EntityId>, WorldError> {
+        let mut entity_ids = vec![
+            world.create_entity().done(),
+            world.create_entity().done(),
+            world.create_entity().done(),
+        ];
+        world.delete_entity(&entity_ids[1])?;
+        world.delete_entity(&entity_ids[0])?;
+        entity_ids.push(world.create_entity().done());
+        entity_ids.push(world.create_entity().done());
+        entity_ids.push(world.create_entity().done());
+        Ok(entity_ids)
+    }
+
+    #[test]
+    pub fn monotonic_allocator() -> Result<(), WorldError> {
+        let mut world = World::with_allocator(MonotonicAllocator::new());
+
+        assert_eq!(spawn_delete_spawn(&mut world)?, vec![0, 1, 2, 3, 4, 5]);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn default_allocator_is_monotonic() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        assert_eq!(spawn_delete_spawn(&mut world)?, vec![0, 1, 2, 3, 4, 5]);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn recycling_allocator() -> Result<(), WorldError> {
+        let mut world = World::with_allocator(RecyclingAllocator::new());
+
+        assert_eq!(spawn_delete_spawn(&mut world)?, vec![0, 1, 2, 0, 1, 3]);
+        assert_eq!(world.num_entities(), 4);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn recycling_allocator_after_truncate() -> Result<(), WorldError> {
+        let mut world = World::with_allocator(RecyclingAllocator::new());
+        for _ in 0..6 {
+            world.create_entity().done();
+        }
+        world.delete_entity(&1)?;
+
+        world.truncate(3);
+
+        assert_eq!(world.create_entity().done(), 1);
+        assert_eq!(world.create_entity().done(), 3);
+        assert_eq!(world.create_entity().done(), 4);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn clear_resets_or_preserves_ids() -> Result<(), WorldError> {
+        let mut world = World::with_allocator(RecyclingAllocator::new());
+        let mut old_ids = vec![];
+        for _ in 0..4 {
+            old_ids.push(world.create_entity().done());
+        }
+
+        world.clear_preserving_id_counter();
+        assert_eq!(world.num_entities(), 0);
+        for _ in 0..4 {
+            let entity_id = world.create_entity().done();
+            assert!(!old_ids.contains(&entity_id));
+        }
+
+        world.clear();
+        assert_eq!(world.num_entities(), 0);
+        assert_eq!(world.create_entity().done(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn reserved_ids_are_not_allocated() -> Result<(), WorldError> {
+        for mut world in [
+            World::new(),
+            World::with_allocator(RecyclingAllocator::new()),
+        ] {
+            world.create_entity().done();
+            let block = world.reserve_ids(5);
+            assert_eq!(block, 1..6);
+
+            let mut spawned = vec![];
+            for _ in 0..5 {
+                spawned.push(world.create_entity().done());
+            }
+            for entity_id in block.clone() {
+                world
+                    .create_entity_with_id(entity_id)?
+                    .with(NodeDrawingComponent(entity_id as i32))
+                    .done();
+            }
+
+            assert!(spawned.iter().all(|entity_id| !block.contains(entity_id)));
+            assert_eq!(world.num_entities(), 11);
+            assert!(world.create_entity_with_id(3).is_err());
+            assert_eq!(
+                world
+                    .get_entity_component::<NodeDrawingComponent>(&3)
+                    .unwrap()
+                    .0,
+                3
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn reserved_ids_stay_reserved() -> Result<(), WorldError> {
+        let mut world = World::with_allocator(RecyclingAllocator::new());
+        let block = world.reserve_ids(3);
+        assert_eq!(block, 0..3);
+
+        world.create_entity_with_id(0)?.done();
+        world.delete_entity(&0)?;
+        let entity_id = world.create_entity().done();
+        assert!(!block.contains(&entity_id));
+
+        for reset in [
+            |world: &mut World| world.clear(),
+            |world: &mut World| {
+                world.truncate(0);
+            },
+            |world: &mut World| {
+                world.reindex();
+            },
+        ] {
+            reset(&mut world);
+            let entity_id = world.create_entity().done();
+            assert!(!block.contains(&entity_id));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn reindex_skips_reserved_ids() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let block = world.reserve_ids(10);
+        assert_eq!(block, 0..10);
+        let first = world.create_entity().with(NodeDrawingComponent(1)).done();
+        let second = world.create_entity().with(NodeDrawingComponent(2)).done();
+        world.create_entity_with_id(4)?.done();
+        world.delete_entity(&first)?;
+
+        let remap = world.reindex();
+
+        assert_eq!(remap[&second], 10);
+        assert_eq!(remap[&4], 4);
+        assert_eq!(world.entity_ids(), vec![4, 10]);
+        assert_eq!(
+            world
+                .get_entity_component::<NodeDrawingComponent>(&10)
+                .unwrap()
+                .0,
+            2
+        );
+        world.create_entity_with_id(0)?.done();
+        assert_eq!(world.create_entity().done(), 11);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn next_entity_id_predicts_spawns() -> Result<(), WorldError> {
+        let mut monotonic = World::new();
+        let mut recycling = World::with_allocator(RecyclingAllocator::new());
+
+        for world in [&mut monotonic, &mut recycling] {
+            for _ in 0..3 {
+                let predicted = world.next_entity_id();
+                assert_eq!(world.next_entity_id(), predicted);
+                assert_eq!(world.create_entity().done(), predicted);
+            }
+            world.delete_entity(&1)?;
+            world.create_entity_with_id(3)?.done();
+
+            for _ in 0..3 {
+                let predicted = world.next_entity_id();
+                assert_eq!(world.create_entity().done(), predicted);
+            }
+        }
+        assert_eq!(monotonic.entity_ids(), vec![0, 2, 3, 4, 5, 6]);
+        assert_eq!(recycling.entity_ids(), vec![0, 1, 2, 3, 4, 5]);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn query_in_creation_order_after_recycling() -> Result<(), WorldError> {
+        let mut world = World::with_allocator(RecyclingAllocator::new());
+        for i in 0..4 {
+            world.create_entity().with(NodeDrawingComponent(i)).done();
+        }
+        world.delete_entity(&1)?;
+        assert_eq!(
+            world.create_entity().with(NodeDrawingComponent(4)).done(),
+            1
+        );
+
+        let by_creation: Vec<i32> = world
+            .query_in_creation_order::<NodeDrawingComponent>()
+            .iter()
+            .map(|component| component.0)
+            .collect();
+        let by_id: Vec<i32> = world
+            .query::<NodeDrawingComponent>()
+            .map(|component| component.0)
+            .collect();
+
+        assert_eq!(by_creation, vec![0, 2, 3, 4]);
+        assert_eq!(by_id, vec![0, 4, 2, 3]);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn end_scope_after_recycling() -> Result<(), WorldError> {
+        let mut world = World::with_allocator(RecyclingAllocator::new());
+        let entity_id = world.create_entity().done();
+        let scope = world.begin_scope();
+        world.add_scoped_component(scope, &entity_id, NodeDrawingComponent(1))?;
+
+        world.delete_entity(&entity_id)?;
+        let recycled = world.create_entity().with(NodeDrawingComponent(99)).done();
+        assert_eq!(recycled, entity_id);
+
+        assert_eq!(world.end_scope(scope), 0);
+        assert_eq!(
+            world
+                .get_entity_component::<NodeDrawingComponent>(&recycled)
+                .unwrap()
+                .0,
+            99
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn occupied_ids_are_skipped() -> Result<(), WorldError> {
+        #[derive(Clone)]
+        struct ForgetfulAllocator(Vec<EntityId>);
+
+        impl IdAllocator for ForgetfulAllocator {
+            fn allocate(&mut self) -> EntityId {
+                self.0.remove(0)
+            }
+        }
+
+        let mut world = World::with_allocator(ForgetfulAllocator(vec![0, 0, 0, 1, 2]));
+
+        let first = world.create_entity().with(NodeDrawingComponent(1)).done();
+        let second = world.create_entity().with(NodeDrawingComponent(2)).done();
+
+        assert_eq!((first, second), (0, 1));
+        assert_eq!(world.num_entities(), 2);
+        assert_eq!(
+            world
+                .get_entity_component::<NodeDrawingComponent>(&first)
+                .unwrap()
+                .0,
+            1
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn custom_allocator() -> Result<(), WorldError> {
+        #[derive(Clone)]
+        struct EvenAllocator(EntityId);
+
+        impl IdAllocator for EvenAllocator {
+            fn allocate(&mut self) -> EntityId {
+                self.0 += 2;
+                self.0 - 2
+            }
+        }
+
+        let mut world = World::with_allocator(EvenAllocator(0));
+
+        assert_eq!(world.create_entity().done(), 0);
+        assert_eq!(world.create_entity().done(), 2);
+
+        let mut snapshot = world.snapshot();
+        assert_eq!(snapshot.create_entity().done(), 4);
+        assert_eq!(world.create_entity().done(), 4);
+
+        Ok(())
+    }
+
+    struct NodeDrawingComponent(i32);
+}
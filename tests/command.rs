@@ -0,0 +1,77 @@
+#[cfg(test)]
+mod tests {
+
+    use std::any::TypeId;
+
+    use enco::command::*;
+    use enco::custom_errors::*;
+    use enco::world::*;
+
+    #[test]
+    pub fn apply_reports_conflicts() {
+        let mut world = World::new();
+        let a = world.create_entity().done();
+        let b = world.create_entity().with(PositionComponent(0)).done();
+        let c = world.create_entity().done();
+
+        let mut commands = CommandBuffer::new();
+        commands.add(a, PositionComponent(1));
+        commands.add(a, PositionComponent(2));
+        commands.despawn(c);
+        commands.add(c, VelocityComponent(3));
+        commands.remove::<PositionComponent>(b);
+        commands.add(b, VelocityComponent(4));
+        assert_eq!(commands.len(), 6);
+
+        let errors = commands.apply(&mut world).unwrap_err();
+
+        assert_eq!(errors.len(), 2);
+        let sources: Vec<&WorldError> = errors
+            .iter()
+            .map(|error| match error {
+                WorldError::Context { source, .. } => source.as_ref(),
+                other => other,
+            })
+            .collect();
+        assert!(matches!(
+            sources[0],
+            WorldError::EntityAlreadyHasComponent(ty) if *ty == TypeId::of::<PositionComponent>()
+        ));
+        assert!(matches!(sources[1], WorldError::EntityDoesNotExist));
+        assert!(errors[1].to_string().starts_with("adding `"));
+
+        assert_eq!(
+            world
+                .get_entity_component::<PositionComponent>(&a)
+                .unwrap()
+                .0,
+            1
+        );
+        assert_eq!(world.num_entities(), 2);
+        assert!(!world.has_component::<PositionComponent>(&b));
+        assert_eq!(
+            world
+                .get_entity_component::<VelocityComponent>(&b)
+                .unwrap()
+                .0,
+            4
+        );
+    }
+
+    #[test]
+    pub fn apply_without_conflicts() {
+        let mut world = World::new();
+        let entity_id = world.create_entity().done();
+
+        let mut commands = CommandBuffer::new();
+        commands.add(entity_id, PositionComponent(1));
+        commands.remove::<PositionComponent>(entity_id);
+
+        assert!(commands.apply(&mut world).is_ok());
+        assert!(!world.has_component::<PositionComponent>(&entity_id));
+        assert!(CommandBuffer::new().is_empty());
+    }
+
+    struct PositionComponent(i32);
+    struct VelocityComponent(i32);
+}
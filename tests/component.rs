@@ -0,0 +1,167 @@
+#[cfg(test)]
+mod tests {
+
+    use std::any::TypeId;
+
+    use enco::component::*;
+    use enco::world::*;
+
+    #[test]
+    pub fn custom_component_name() {
+        let mut world = World::new();
+
+        world.register_component::<PositionComponent>();
+        world.register_component::<VelocityComponent>();
+
+        assert_eq!(
+            world.component_name::<PositionComponent>(),
+            Some("Position")
+        );
+        assert_eq!(
+            world.component_name::<VelocityComponent>(),
+            Some(std::any::type_name::<VelocityComponent>())
+        );
+        assert_eq!(world.component_name::<TagComponent>(), None);
+
+        let entity_id = world
+            .create_entity()
+            .with(PositionComponent(1))
+            .with(TagComponent)
+            .done();
+        assert_eq!(
+            world
+                .get_entity_component::<PositionComponent>(&entity_id)
+                .unwrap()
+                .0,
+            1
+        );
+    }
+
+    #[test]
+    pub fn snapshot_keeps_component_names() {
+        let mut world = World::new();
+
+        world.register_component::<PositionComponent>();
+
+        assert_eq!(
+            world.snapshot().component_name::<PositionComponent>(),
+            Some("Position")
+        );
+    }
+
+    #[test]
+    pub fn registered_and_reserved_queries() {
+        let mut registered = World::new();
+        registered.register_component::<PositionComponent>();
+        registered.register_component::<VelocityComponent>();
+        registered.reserve_entities(100);
+
+        let mut unregistered = World::new();
+
+        for world in [&mut registered, &mut unregistered] {
+            for i in 0..100 {
+                let mut builder = world.create_entity();
+                builder.with(PositionComponent(i));
+                if i % 4 == 0 {
+                    builder.with(VelocityComponent);
+                }
+                if i % 5 == 0 {
+                    builder.with(TagComponent);
+                }
+                builder.done();
+            }
+        }
+
+        for world in [&registered, &unregistered] {
+            let mut moving: Vec<i32> = world
+                .query2::<PositionComponent, VelocityComponent>()
+                .map(|(position, _velocity)| position.0)
+                .collect();
+            moving.sort();
+            assert_eq!(moving, (0..100).step_by(4).collect::<Vec<i32>>());
+            assert_eq!(world.count::<PositionComponent>(), 100);
+            assert_eq!(world.count::<TagComponent>(), 20);
+        }
+    }
+
+    #[test]
+    pub fn access_through_component_key() {
+        let position = ComponentKey::<PositionComponent>::new();
+        let mut world = World::new();
+        let entity_id = world.create_entity().with(PositionComponent(1)).done();
+
+        assert_eq!(world.get(&entity_id, position).unwrap().0, 1);
+
+        world
+            .set(&entity_id, position, PositionComponent(2))
+            .unwrap();
+        world.get_mut(&entity_id, position).unwrap().0 += 1;
+        assert_eq!(world.get(&entity_id, position).unwrap().0, 3);
+
+        world.remove(&entity_id, position).unwrap();
+        assert!(world.get(&entity_id, position).is_none());
+        assert!(world.remove(&entity_id, position).is_err());
+    }
+
+    #[test]
+    pub fn owned_types_are_component_types() {
+        assert_component_type::<PositionComponent>();
+        assert_component_type::<String>();
+        assert_component_type::<&'static str>();
+        assert_component_type::<Vec<Box<TagComponent>>>();
+    }
+
+    #[test]
+    pub fn type_tag_by_type_id() {
+        let mut world = World::new();
+        world.register_component::<PositionComponent>();
+
+        assert_eq!(
+            world.type_tag(TypeId::of::<PositionComponent>()),
+            Some("Position")
+        );
+        assert_eq!(world.type_tag(TypeId::of::<VelocityComponent>()), None);
+    }
+
+    #[test]
+    pub fn unused_registered_types() {
+        let mut world = World::new();
+        world.register_component::<PositionComponent>();
+        world.register_component::<VelocityComponent>();
+        world.register_component::<TagComponent>();
+        let entity_id = world
+            .create_entity()
+            .with(PositionComponent(1))
+            .with(VelocityComponent)
+            .done();
+
+        assert_eq!(
+            world.unused_registered_types(),
+            vec![TypeId::of::<TagComponent>()]
+        );
+
+        world
+            .delete_component::<VelocityComponent>(&entity_id)
+            .unwrap();
+        let mut expected = vec![
+            TypeId::of::<VelocityComponent>(),
+            TypeId::of::<TagComponent>(),
+        ];
+        expected.sort();
+        assert_eq!(world.unused_registered_types(), expected);
+    }
+
+    struct PositionComponent(i32);
+    struct VelocityComponent;
+    struct TagComponent;
+
+    impl Component for PositionComponent {
+        fn name() -> &'static str {
+            "Position"
+        }
+    }
+
+    impl Component for VelocityComponent {}
+
+    impl Component for TagComponent {}
+}
@@ -0,0 +1,31 @@
+#[cfg(test)]
+mod tests {
+
+    use std::error::Error;
+
+    use enco::custom_errors::*;
+    use enco::world::*;
+
+    #[test]
+    pub fn error_context_is_displayed() {
+        let mut world = World::new();
+        let entity_id = world.create_entity().done();
+
+        let error = world
+            .delete_component::<PositionComponent>(&entity_id)
+            .context("moving the camera")
+            .context("running the camera system")
+            .unwrap_err();
+
+        let message = error.to_string();
+        assert!(message.starts_with("running the camera system: moving the camera: "));
+        assert!(message.contains("does not have component"));
+
+        let inner = error.source().unwrap().to_string();
+        assert!(inner.starts_with("moving the camera: "));
+
+        assert!(world.delete_entity(&entity_id).context("unused").is_ok());
+    }
+
+    struct PositionComponent;
+}
@@ -0,0 +1,42 @@
+#[cfg(test)]
+mod tests {
+
+    use enco::filter::*;
+    use enco::world::*;
+
+    #[test]
+    pub fn compose_filters_at_runtime() {
+        let mut world = World::new();
+        let moving = world
+            .create_entity()
+            .with(PositionComponent)
+            .with(VelocityComponent)
+            .done();
+        let frozen = world
+            .create_entity()
+            .with(PositionComponent)
+            .with(VelocityComponent)
+            .with(FrozenComponent)
+            .done();
+        let still = world.create_entity().with(PositionComponent).done();
+        let empty = world.create_entity().done();
+
+        let filter = And(
+            Box::new(With::of::<VelocityComponent>()),
+            Box::new(Without::of::<FrozenComponent>()),
+        );
+        let ids: Vec<EntityId> = world.query_filtered(&filter).collect();
+        assert_eq!(ids, vec![moving]);
+
+        let filter = Or(
+            Box::new(With::of::<FrozenComponent>()),
+            Box::new(Not(Box::new(With::of::<VelocityComponent>()))),
+        );
+        let ids: Vec<EntityId> = world.query_filtered(&filter).collect();
+        assert_eq!(ids, vec![frozen, still, empty]);
+    }
+
+    struct PositionComponent;
+    struct VelocityComponent;
+    struct FrozenComponent;
+}
@@ -0,0 +1,39 @@
+#[cfg(test)]
+mod tests {
+
+    use enco::view::*;
+    use enco::world::*;
+
+    fn sum_nodes(view: WorldView) -> i32 {
+        view.query::<NodeDrawingComponent>()
+            .map(|node| node.0)
+            .sum()
+    }
+
+    #[test]
+    pub fn read_through_view() {
+        let mut world = World::new();
+
+        let entity_id_1 = world.create_entity().with(NodeDrawingComponent(1)).done();
+        world.create_entity().with(NodeDrawingComponent(2)).done();
+        world.create_entity().with(ConnectorDrawingComponent).done();
+
+        assert_eq!(sum_nodes(world.view()), 3);
+
+        let view = world.view();
+        assert_eq!(view.num_entities(), 3);
+        assert_eq!(view.iter().count(), 3);
+        assert_eq!(view.count::<NodeDrawingComponent>(), 2);
+        assert!(view.has_component::<NodeDrawingComponent>(&entity_id_1));
+        assert!(!view.has_component::<ConnectorDrawingComponent>(&entity_id_1));
+        assert_eq!(
+            view.get_entity_component::<NodeDrawingComponent>(&entity_id_1)
+                .unwrap()
+                .0,
+            1
+        );
+    }
+
+    struct NodeDrawingComponent(i32);
+    struct ConnectorDrawingComponent;
+}
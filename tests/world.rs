@@ -3,6 +3,7 @@ mod tests {
 
     use enco::custom_errors::*;
     use enco::world::*;
+    use std::{cell::RefCell, rc::Rc};
 
     #[test]
     pub fn get_entity_component() {
@@ -29,7 +30,7 @@ mod tests {
 
         let entity_id_1 = world.create_entity().with(NodeDrawingComponent(1)).done();
 
-        let mut c1 = world
+        let c1 = world
             .get_entity_component_mut::<NodeDrawingComponent>(&entity_id_1)
             .unwrap();
         c1.0 = 10;
@@ -191,10 +192,4005 @@ mod tests {
         assert!(world
             .get_entity_component::<ConnectorDrawingComponent>(&entity_id)
             .is_some());
+        assert!(matches!(
+            world.add_component(&100, NodeDrawingComponent(1)),
+            Err(WorldError::EntityDoesNotExist)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn query_two_components() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        world
+            .create_entity()
+            .with(NodeDrawingComponent(1))
+            .with(ConnectorDrawingComponent(10))
+            .done();
+        world.create_entity().with(NodeDrawingComponent(2)).done();
+        world
+            .create_entity()
+            .with(ConnectorDrawingComponent(20))
+            .done();
+        world
+            .create_entity()
+            .with(NodeDrawingComponent(3))
+            .with(ConnectorDrawingComponent(30))
+            .done();
+
+        let mut values: Vec<(i32, i32)> = world
+            .query2::<NodeDrawingComponent, ConnectorDrawingComponent>()
+            .map(|(node, connector)| (node.0, connector.0))
+            .collect();
+        values.sort();
+
+        assert_eq!(values, vec![(1, 10), (3, 30)]);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn query_three_components() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        world
+            .create_entity()
+            .with(NodeDrawingComponent(1))
+            .with(ConnectorDrawingComponent(10))
+            .with(PortComponent(100))
+            .done();
+        world
+            .create_entity()
+            .with(NodeDrawingComponent(2))
+            .with(ConnectorDrawingComponent(20))
+            .done();
+        world
+            .create_entity()
+            .with(NodeDrawingComponent(3))
+            .with(PortComponent(300))
+            .done();
+        world
+            .create_entity()
+            .with(ConnectorDrawingComponent(40))
+            .with(PortComponent(400))
+            .done();
+        world.create_entity().with(PortComponent(500)).done();
+        world
+            .create_entity()
+            .with(NodeDrawingComponent(6))
+            .with(ConnectorDrawingComponent(60))
+            .with(PortComponent(600))
+            .done();
+
+        let mut values: Vec<(i32, i32, i32)> = world
+            .query3::<NodeDrawingComponent, ConnectorDrawingComponent, PortComponent>()
+            .map(|(node, connector, port)| (node.0, connector.0, port.0))
+            .collect();
+        values.sort();
+
+        assert_eq!(values, vec![(1, 10, 100), (6, 60, 600)]);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn get_two_components_mut() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let entity_id = world
+            .create_entity()
+            .with(PositionComponent(1))
+            .with(VelocityComponent(2))
+            .done();
+        let lonely_id = world.create_entity().with(PositionComponent(5)).done();
+
+        let (position, velocity) = world
+            .get_two_components_mut::<PositionComponent, VelocityComponent>(&entity_id)
+            .unwrap();
+        position.0 += velocity.0;
+        velocity.0 = 0;
+
+        assert_eq!(
+            world
+                .get_entity_component::<PositionComponent>(&entity_id)
+                .unwrap()
+                .0,
+            3
+        );
+        assert_eq!(
+            world
+                .get_entity_component::<VelocityComponent>(&entity_id)
+                .unwrap()
+                .0,
+            0
+        );
+
+        assert!(world
+            .get_two_components_mut::<PositionComponent, VelocityComponent>(&lonely_id)
+            .is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "mutably twice")]
+    pub fn get_two_components_mut_of_same_type() {
+        let mut world = World::new();
+        let entity_id = world.create_entity().with(PositionComponent(1)).done();
+
+        world.get_two_components_mut::<PositionComponent, PositionComponent>(&entity_id);
+    }
+
+    #[test]
+    pub fn query_two_components_mut() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let moving_1 = world
+            .create_entity()
+            .with(PositionComponent(0))
+            .with(VelocityComponent(1))
+            .done();
+        let moving_2 = world
+            .create_entity()
+            .with(PositionComponent(10))
+            .with(VelocityComponent(-2))
+            .done();
+        let still = world.create_entity().with(PositionComponent(100)).done();
+
+        for _ in 0..3 {
+            for (position, velocity) in world.query2_mut::<PositionComponent, VelocityComponent>() {
+                position.0 += velocity.0;
+            }
+        }
+
+        let position_of = |entity_id: &EntityId| {
+            world
+                .get_entity_component::<PositionComponent>(entity_id)
+                .unwrap()
+                .0
+        };
+        assert_eq!(position_of(&moving_1), 3);
+        assert_eq!(position_of(&moving_2), 4);
+        assert_eq!(position_of(&still), 100);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "mutably twice")]
+    pub fn query_two_components_mut_of_same_type() {
+        let mut world = World::new();
+        world.create_entity().with(PositionComponent(1)).done();
+
+        let _ = world.query2_mut::<PositionComponent, PositionComponent>();
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "mutably twice")]
+    pub fn integrate_same_type() {
+        let mut world = World::new();
+        world.create_entity().with(PositionComponent(1)).done();
+
+        world.integrate::<PositionComponent, PositionComponent, _>(|a, b| a.0 += b.0);
+    }
+
+    #[test]
+    pub fn on_add_hook() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let added = Rc::new(RefCell::new(Vec::new()));
+
+        let recorder = Rc::clone(&added);
+        world.register_on_add::<NodeDrawingComponent>(move |entity_id| {
+            recorder.borrow_mut().push(entity_id);
+        });
+
+        let built_id = world.create_entity().with(NodeDrawingComponent(1)).done();
+        let other_id = world
+            .create_entity()
+            .with(ConnectorDrawingComponent(1))
+            .done();
+        world.add_component(&other_id, NodeDrawingComponent(2))?;
+
+        assert_eq!(*added.borrow(), vec![built_id, other_id]);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn on_remove_hook() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let removed = Rc::new(RefCell::new(Vec::new()));
+
+        let recorder = Rc::clone(&removed);
+        world.register_on_remove::<NodeDrawingComponent>(move |entity_id| {
+            recorder.borrow_mut().push(entity_id);
+        });
+
+        let entity_id_1 = world
+            .create_entity()
+            .with(NodeDrawingComponent(1))
+            .with(ConnectorDrawingComponent(1))
+            .done();
+        let entity_id_2 = world.create_entity().with(NodeDrawingComponent(2)).done();
+
+        world.delete_component::<ConnectorDrawingComponent>(&entity_id_1)?;
+        assert!(removed.borrow().is_empty());
+
+        world.delete_component::<NodeDrawingComponent>(&entity_id_1)?;
+        world.delete_entity(&entity_id_2)?;
+
+        assert_eq!(*removed.borrow(), vec![entity_id_1, entity_id_2]);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn parent_child_hierarchy() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let root = world.create_entity().done();
+        let child_1 = world.create_entity().done();
+        let child_2 = world.create_entity().done();
+        let grandchild = world.create_entity().done();
+
+        world.set_parent(child_1, root)?;
+        world.set_parent(child_2, root)?;
+        world.set_parent(grandchild, child_1)?;
+
+        assert_eq!(world.children(&root), vec![child_1, child_2]);
+        assert_eq!(world.children(&child_1), vec![grandchild]);
+        assert!(world.children(&grandchild).is_empty());
+        assert_eq!(world.parent(&grandchild), Some(child_1));
+        assert_eq!(world.parent(&root), None);
+
+        world.set_parent(grandchild, child_2)?;
+        assert!(world.children(&child_1).is_empty());
+        assert_eq!(world.children(&child_2), vec![grandchild]);
+
+        assert!(world.set_parent(grandchild, 100).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn set_parent_rejects_cycles() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let root = world.create_entity().done();
+        let child = world.create_entity().done();
+        let grandchild = world.create_entity().done();
+        world.set_parent(child, root)?;
+        world.set_parent(grandchild, child)?;
+
+        assert!(matches!(
+            world.set_parent(root, root),
+            Err(WorldError::ParentCycle(a, b)) if a == root && b == root
+        ));
+        assert!(matches!(
+            world.set_parent(root, grandchild),
+            Err(WorldError::ParentCycle(a, b)) if a == root && b == grandchild
+        ));
+        assert_eq!(world.parent(&root), None);
+        assert_eq!(world.children(&grandchild), vec![]);
+
+        world.despawn_recursive(root)?;
+        assert_eq!(world.num_entities(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn delete_entity_orphans_children() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let root = world.create_entity().done();
+        let child = world.create_entity().done();
+        let grandchild = world.create_entity().done();
+        world.set_parent(child, root)?;
+        world.set_parent(grandchild, child)?;
+
+        world.delete_entity(&child)?;
+
+        assert_eq!(world.num_entities(), 2);
+        assert!(world.children(&root).is_empty());
+        assert_eq!(world.parent(&grandchild), None);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn despawn_recursive() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let root = world.create_entity().done();
+        let child_1 = world.create_entity().with(NodeDrawingComponent(1)).done();
+        let child_2 = world.create_entity().done();
+        let grandchild = world.create_entity().done();
+        let unrelated = world.create_entity().done();
+        world.set_parent(child_1, root)?;
+        world.set_parent(child_2, root)?;
+        world.set_parent(grandchild, child_1)?;
+
+        world.despawn_recursive(child_1)?;
+
+        assert_eq!(world.num_entities(), 3);
+        assert!(world.num_components(&child_1).is_err());
+        assert!(world.num_components(&grandchild).is_err());
+        assert_eq!(world.children(&root), vec![child_2]);
+
+        world.despawn_recursive(root)?;
+
+        assert_eq!(world.num_entities(), 1);
+        assert!(world.num_components(&unrelated).is_ok());
+        assert!(world.despawn_recursive(root).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn query_any_of_two_components() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let node = world.create_entity().with(NodeDrawingComponent(1)).done();
+        let connector = world
+            .create_entity()
+            .with(ConnectorDrawingComponent(1))
+            .done();
+        let both = world
+            .create_entity()
+            .with(NodeDrawingComponent(2))
+            .with(ConnectorDrawingComponent(2))
+            .done();
+        world.create_entity().with(PortComponent(1)).done();
+        world.create_entity().done();
+
+        let mut entity_ids: Vec<EntityId> = world
+            .query_any_of::<NodeDrawingComponent, ConnectorDrawingComponent>()
+            .collect();
+        entity_ids.sort();
+
+        assert_eq!(entity_ids, vec![node, connector, both]);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn has_component() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let entity_id = world.create_entity().with(NodeDrawingComponent(1)).done();
+
+        assert!(world.has_component::<NodeDrawingComponent>(&entity_id));
+        assert!(!world.has_component::<ConnectorDrawingComponent>(&entity_id));
+        assert!(!world.has_component::<NodeDrawingComponent>(&100));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn count_components() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        assert_eq!(world.count::<NodeDrawingComponent>(), 0);
+
+        world.create_entity().with(NodeDrawingComponent(1)).done();
+        world.create_entity().with(NodeDrawingComponent(2)).done();
+        world
+            .create_entity()
+            .with(ConnectorDrawingComponent(1))
+            .done();
+
+        assert_eq!(world.count::<NodeDrawingComponent>(), 2);
+        assert_eq!(world.count::<ConnectorDrawingComponent>(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn indexed_queries_match_naive_scan() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        for i in 0..60 {
+            let entity_id = world.create_entity().done();
+            if i % 2 == 0 {
+                world.add_component(&entity_id, NodeDrawingComponent(i))?;
+            }
+            if i % 3 == 0 {
+                world.add_component(&entity_id, ConnectorDrawingComponent(i))?;
+            }
+            if i % 5 == 0 {
+                world.add_component(&entity_id, PortComponent(i))?;
+            }
+        }
+        for i in (0..60).step_by(7) {
+            world.delete_component::<ConnectorDrawingComponent>(&i).ok();
+        }
+        for i in (0..60).step_by(11) {
+            world.delete_entity(&i)?;
+        }
+
+        let mut naive_2: Vec<i32> = world
+            .iter()
+            .filter_map(|entity_id| {
+                world.get_entity_component::<ConnectorDrawingComponent>(entity_id)?;
+                Some(
+                    world
+                        .get_entity_component::<NodeDrawingComponent>(entity_id)?
+                        .0,
+                )
+            })
+            .collect();
+        let mut indexed_2: Vec<i32> = world
+            .query2::<NodeDrawingComponent, ConnectorDrawingComponent>()
+            .map(|(node, _connector)| node.0)
+            .collect();
+        naive_2.sort();
+        indexed_2.sort();
+        assert_eq!(indexed_2, naive_2);
+
+        let mut naive_3: Vec<i32> = world
+            .iter()
+            .filter_map(|entity_id| {
+                world.get_entity_component::<ConnectorDrawingComponent>(entity_id)?;
+                world.get_entity_component::<PortComponent>(entity_id)?;
+                Some(
+                    world
+                        .get_entity_component::<NodeDrawingComponent>(entity_id)?
+                        .0,
+                )
+            })
+            .collect();
+        let mut indexed_3: Vec<i32> = world
+            .query3::<NodeDrawingComponent, ConnectorDrawingComponent, PortComponent>()
+            .map(|(node, _connector, _port)| node.0)
+            .collect();
+        naive_3.sort();
+        indexed_3.sort();
+        assert_eq!(indexed_3, naive_3);
+        assert_eq!(indexed_3, vec![30]);
+
+        assert_eq!(
+            world.count::<PortComponent>(),
+            world.query::<PortComponent>().count()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn snapshot_world() -> Result<(), WorldError> {
+        let mut world = World::new();
+        world.register_cloneable::<LabelComponent>();
+
+        let parent = world
+            .create_entity()
+            .with(LabelComponent("parent".to_string()))
+            .with(NodeDrawingComponent(1))
+            .done();
+        let child = world
+            .create_entity()
+            .with(LabelComponent("child".to_string()))
+            .done();
+        world.set_parent(child, parent)?;
+
+        let snapshot = world.snapshot();
+        world
+            .get_entity_component_mut::<LabelComponent>(&parent)
+            .unwrap()
+            .0 = "changed".to_string();
+
+        assert_eq!(snapshot.num_entities(), 2);
+        assert_eq!(
+            snapshot
+                .get_entity_component::<LabelComponent>(&parent)
+                .unwrap()
+                .0,
+            "parent"
+        );
+        assert!(!snapshot.has_component::<NodeDrawingComponent>(&parent));
+        assert_eq!(snapshot.children(&parent), vec![child]);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn clone_selection() -> Result<(), WorldError> {
+        let mut world = World::new();
+        world.register_cloneable::<LabelComponent>();
+
+        let ids: Vec<EntityId> = (0..5)
+            .map(|i| {
+                world
+                    .create_entity()
+                    .with(LabelComponent(format!("entity {}", i)))
+                    .done()
+            })
+            .collect();
+
+        let (selection, id_map) = world.clone_selection([ids[1], ids[3]]);
+
+        assert_eq!(selection.num_entities(), 2);
+        assert_eq!(id_map.len(), 2);
+        assert_eq!(
+            selection
+                .get_entity_component::<LabelComponent>(&id_map[&ids[1]])
+                .unwrap()
+                .0,
+            "entity 1"
+        );
+        assert_eq!(
+            selection
+                .get_entity_component::<LabelComponent>(&id_map[&ids[3]])
+                .unwrap()
+                .0,
+            "entity 3"
+        );
+        assert_eq!(world.num_entities(), 5);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn clone_selection_keeps_inner_relationships() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let ids: Vec<EntityId> = (0..4).map(|_| world.create_entity().done()).collect();
+        world.set_parent(ids[1], ids[0])?;
+        world.set_parent(ids[2], ids[1])?;
+        world.set_parent(ids[3], ids[1])?;
+
+        let (selection, id_map) = world.clone_selection([ids[3], ids[1], ids[2]]);
+
+        assert_eq!(
+            selection.children(&id_map[&ids[1]]),
+            vec![id_map[&ids[2]], id_map[&ids[3]]]
+        );
+        assert_eq!(selection.parent(&id_map[&ids[3]]), Some(id_map[&ids[1]]));
+        assert_eq!(selection.parent(&id_map[&ids[1]]), None);
+        assert!(selection.validate().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn delete_while_looping_over_entity_ids() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        for i in 0..6 {
+            world.create_entity().with(NodeDrawingComponent(i)).done();
+        }
+
+        let entity_ids = world.entity_ids();
+        assert_eq!(entity_ids, vec![0, 1, 2, 3, 4, 5]);
+
+        for entity_id in entity_ids.iter().step_by(2) {
+            world.delete_entity(entity_id)?;
+        }
+
+        assert_eq!(world.entity_ids(), vec![1, 3, 5]);
 
         Ok(())
     }
 
+    #[test]
+    pub fn query_optional_component() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        for i in 0..6 {
+            let entity_id = world.create_entity().done();
+            if i % 2 == 0 {
+                world.add_component(&entity_id, NodeDrawingComponent(i))?;
+            }
+        }
+
+        let mut column: Vec<(EntityId, Option<i32>)> = world
+            .query_optional::<NodeDrawingComponent>()
+            .map(|(entity_id, node)| (entity_id, node.map(|node| node.0)))
+            .collect();
+        column.sort();
+
+        assert_eq!(
+            column,
+            vec![
+                (0, Some(0)),
+                (1, None),
+                (2, Some(2)),
+                (3, None),
+                (4, Some(4)),
+                (5, None)
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn drain_components() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let entity_id_1 = world.create_entity().with(NodeDrawingComponent(1)).done();
+        let entity_id_2 = world
+            .create_entity()
+            .with(NodeDrawingComponent(2))
+            .with(ConnectorDrawingComponent(2))
+            .done();
+        let entity_id_3 = world.create_entity().with(NodeDrawingComponent(3)).done();
+        world
+            .create_entity()
+            .with(ConnectorDrawingComponent(4))
+            .done();
+
+        let mut drained: Vec<(EntityId, i32)> = world
+            .drain::<NodeDrawingComponent>()
+            .into_iter()
+            .map(|(entity_id, node)| (entity_id, node.0))
+            .collect();
+        drained.sort();
+
+        assert_eq!(
+            drained,
+            vec![(entity_id_1, 1), (entity_id_2, 2), (entity_id_3, 3)]
+        );
+        assert_eq!(world.query::<NodeDrawingComponent>().count(), 0);
+        assert_eq!(world.count::<ConnectorDrawingComponent>(), 2);
+        assert_eq!(world.num_entities(), 4);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn build_entity_with_several_components() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let entity_id = world
+            .create_entity()
+            .with(NodeDrawingComponent(1))
+            .with(ConnectorDrawingComponent(2))
+            .with(PortComponent(3))
+            .done();
+
+        assert_eq!(world.num_components(&entity_id)?, 3);
+        assert_eq!(
+            world
+                .get_entity_component::<NodeDrawingComponent>(&entity_id)
+                .unwrap()
+                .0,
+            1
+        );
+        assert_eq!(
+            world
+                .get_entity_component::<ConnectorDrawingComponent>(&entity_id)
+                .unwrap()
+                .0,
+            2
+        );
+        assert_eq!(
+            world
+                .get_entity_component::<PortComponent>(&entity_id)
+                .unwrap()
+                .0,
+            3
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn builders_do_not_share_entities() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let mut first = world.create_entity();
+        first.with(NodeDrawingComponent(1));
+        let first_id = first.done();
+
+        world.create_entity().with(PortComponent(1));
+
+        let second_id = world
+            .create_entity()
+            .with(ConnectorDrawingComponent(2))
+            .done();
+
+        assert_ne!(first_id, second_id);
+        assert_eq!(world.num_entities(), 3);
+        assert_eq!(world.num_components(&first_id)?, 1);
+        assert_eq!(world.num_components(&second_id)?, 1);
+        assert_eq!(world.count::<PortComponent>(), 1);
+        assert!(!world.has_component::<PortComponent>(&second_id));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn any_component_matches() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        assert!(!world.any::<NodeDrawingComponent, _>(|_| true));
+
+        world.create_entity().with(NodeDrawingComponent(1)).done();
+        world.create_entity().with(NodeDrawingComponent(2)).done();
+        world
+            .create_entity()
+            .with(ConnectorDrawingComponent(3))
+            .done();
+
+        assert!(world.any::<NodeDrawingComponent, _>(|node| node.0 == 2));
+        assert!(!world.any::<NodeDrawingComponent, _>(|node| node.0 == 3));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn all_components_match() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        assert!(world.all::<NodeDrawingComponent, _>(|_| false));
+
+        world.create_entity().with(NodeDrawingComponent(1)).done();
+        world.create_entity().with(NodeDrawingComponent(2)).done();
+        world
+            .create_entity()
+            .with(ConnectorDrawingComponent(-1))
+            .done();
+
+        assert!(world.all::<NodeDrawingComponent, _>(|node| node.0 > 0));
+        assert!(!world.all::<NodeDrawingComponent, _>(|node| node.0 > 1));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn group_components_by_key() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        for i in 0..5 {
+            world.create_entity().with(NodeDrawingComponent(i)).done();
+        }
+        world
+            .create_entity()
+            .with(ConnectorDrawingComponent(6))
+            .done();
+
+        let groups = world.group_by::<NodeDrawingComponent, _, _>(|node| node.0 % 2 == 0);
+
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[&true], vec![0, 2, 4]);
+        assert_eq!(groups[&false], vec![1, 3]);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn swap_components_between_entities() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let a = world
+            .create_entity()
+            .with(PositionComponent(1))
+            .with(NodeDrawingComponent(1))
+            .done();
+        let b = world
+            .create_entity()
+            .with(PositionComponent(2))
+            .with(NodeDrawingComponent(2))
+            .done();
+
+        world.swap_components::<PositionComponent>(&a, &b)?;
+
+        let position = |entity_id| {
+            world
+                .get_entity_component::<PositionComponent>(&entity_id)
+                .unwrap()
+                .0
+        };
+        let node = |entity_id| {
+            world
+                .get_entity_component::<NodeDrawingComponent>(&entity_id)
+                .unwrap()
+                .0
+        };
+        assert_eq!((position(a), position(b)), (2, 1));
+        assert_eq!((node(a), node(b)), (1, 2));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn swap_components_with_itself() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let a = world.create_entity().with(PositionComponent(1)).done();
+
+        world.swap_components::<PositionComponent>(&a, &a)?;
+
+        assert_eq!(
+            world
+                .get_entity_component::<PositionComponent>(&a)
+                .unwrap()
+                .0,
+            1
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn swap_missing_components() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let a = world.create_entity().with(PositionComponent(1)).done();
+        let b = world.create_entity().done();
+
+        assert!(matches!(
+            world.swap_components::<PositionComponent>(&a, &b),
+            Err(WorldError::EntityDoesNotHaveComponent(_))
+        ));
+        assert!(matches!(
+            world.swap_components::<PositionComponent>(&a, &100),
+            Err(WorldError::EntityDoesNotExist)
+        ));
+        assert_eq!(
+            world
+                .get_entity_component::<PositionComponent>(&a)
+                .unwrap()
+                .0,
+            1
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn shrink_after_mass_deletion() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        for i in 0..500 {
+            world
+                .create_entity()
+                .with(NodeDrawingComponent(i))
+                .with(ConnectorDrawingComponent(i))
+                .done();
+        }
+        for entity_id in world.entity_ids() {
+            if entity_id % 100 != 0 {
+                world.delete_entity(&entity_id)?;
+            } else {
+                world.delete_component::<ConnectorDrawingComponent>(&entity_id)?;
+            }
+        }
+
+        world.shrink_to_fit();
+
+        assert_eq!(world.entity_ids(), vec![0, 100, 200, 300, 400]);
+        for entity_id in world.entity_ids() {
+            assert_eq!(world.num_components(&entity_id)?, 1);
+            assert_eq!(
+                world
+                    .get_entity_component::<NodeDrawingComponent>(&entity_id)
+                    .unwrap()
+                    .0 as usize,
+                entity_id
+            );
+        }
+        assert_eq!(world.count::<NodeDrawingComponent>(), 5);
+        assert_eq!(world.count::<ConnectorDrawingComponent>(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn send_and_clear_events() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        world.send_event(CollisionEvent(0, 1));
+        world.send_event(CollisionEvent(1, 2));
+        world.send_event(CollisionEvent(2, 3));
+
+        let collisions: Vec<(EntityId, EntityId)> = world
+            .read_events::<CollisionEvent>()
+            .map(|collision| (collision.0, collision.1))
+            .collect();
+        assert_eq!(collisions, vec![(0, 1), (1, 2), (2, 3)]);
+        assert_eq!(world.read_events::<NodeDrawingComponent>().count(), 0);
+        assert_eq!(world.num_entities(), 0);
+
+        world.clear_events();
+
+        assert_eq!(world.read_events::<CollisionEvent>().count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn expect_component() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let entity_id = world.create_entity().with(NodeDrawingComponent(1)).done();
+
+        assert_eq!(
+            world
+                .expect_component::<NodeDrawingComponent>(&entity_id)?
+                .0,
+            1
+        );
+        assert!(matches!(
+            world.expect_component::<ConnectorDrawingComponent>(&entity_id),
+            Err(WorldError::EntityDoesNotHaveComponent(type_id))
+                if type_id == std::any::TypeId::of::<ConnectorDrawingComponent>()
+        ));
+        assert!(matches!(
+            world.expect_component::<NodeDrawingComponent>(&100),
+            Err(WorldError::EntityDoesNotExist)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn expect_component_mut() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let entity_id = world.create_entity().with(NodeDrawingComponent(1)).done();
+
+        world
+            .expect_component_mut::<NodeDrawingComponent>(&entity_id)?
+            .0 = 5;
+        assert_eq!(
+            world
+                .expect_component::<NodeDrawingComponent>(&entity_id)?
+                .0,
+            5
+        );
+        assert!(matches!(
+            world.expect_component_mut::<ConnectorDrawingComponent>(&entity_id),
+            Err(WorldError::EntityDoesNotHaveComponent(_))
+        ));
+        assert!(matches!(
+            world.expect_component_mut::<NodeDrawingComponent>(&100),
+            Err(WorldError::EntityDoesNotExist)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn join_two_components() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let mut expected = Vec::new();
+        for i in 0..10 {
+            let entity_id = world.create_entity().with(PositionComponent(i)).done();
+            if i % 3 == 0 {
+                world.add_component(&entity_id, VelocityComponent(i * 10))?;
+                expected.push((entity_id, i, i * 10));
+            }
+        }
+        world.create_entity().with(VelocityComponent(-1)).done();
+
+        let mut joined: Vec<(EntityId, i32, i32)> = world
+            .join::<PositionComponent, VelocityComponent>()
+            .map(|(entity_id, position, velocity)| (entity_id, position.0, velocity.0))
+            .collect();
+        joined.sort();
+
+        assert_eq!(joined, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn delete_entity_cleans_up_lookups() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let parent = world.create_entity().done();
+        let entity_id = world
+            .create_entity()
+            .with(NodeDrawingComponent(1))
+            .with(ConnectorDrawingComponent(1))
+            .with(TagComponent)
+            .done();
+        let child = world.create_entity().done();
+        world.set_parent(entity_id, parent)?;
+        world.set_parent(child, entity_id)?;
+
+        world.delete_entity(&entity_id)?;
+
+        assert!(world.children(&parent).is_empty());
+        assert!(world.children(&entity_id).is_empty());
+        assert_eq!(world.parent(&entity_id), None);
+        assert_eq!(world.parent(&child), None);
+        assert!(!world.has_component::<TagComponent>(&entity_id));
+        assert_eq!(world.count::<TagComponent>(), 0);
+        assert_eq!(world.count::<NodeDrawingComponent>(), 0);
+        assert_eq!(
+            world
+                .query2::<NodeDrawingComponent, ConnectorDrawingComponent>()
+                .count(),
+            0
+        );
+        assert_eq!(
+            world.join::<NodeDrawingComponent, TagComponent>().count(),
+            0
+        );
+        assert!(world.entity_ids().iter().all(|id| *id != entity_id));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn has_component_by_type() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let entity_ids = [
+            world.create_entity().with(NodeDrawingComponent(1)).done(),
+            world
+                .create_entity()
+                .with(ConnectorDrawingComponent(1))
+                .done(),
+            world.create_entity().done(),
+            100,
+        ];
+
+        let node_type = std::any::TypeId::of::<NodeDrawingComponent>();
+        let connector_type = std::any::TypeId::of::<ConnectorDrawingComponent>();
+        for entity_id in &entity_ids {
+            assert_eq!(
+                world.has_component::<NodeDrawingComponent>(entity_id),
+                world.has_component_by_type(entity_id, node_type)
+            );
+            assert_eq!(
+                world.has_component::<ConnectorDrawingComponent>(entity_id),
+                world.has_component_by_type(entity_id, connector_type)
+            );
+        }
+        assert!(world.has_component_by_type(&entity_ids[0], node_type));
+        assert!(!world.has_component_by_type(&entity_ids[0], connector_type));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn diff_against_snapshot() -> Result<(), WorldError> {
+        let mut world = World::new();
+        world.register_cloneable::<LabelComponent>();
+        world.register_comparable::<LabelComponent>();
+
+        let unchanged = world
+            .create_entity()
+            .with(LabelComponent("unchanged".to_string()))
+            .done();
+        let mutated = world
+            .create_entity()
+            .with(LabelComponent("before".to_string()))
+            .done();
+        let deleted = world
+            .create_entity()
+            .with(LabelComponent("deleted".to_string()))
+            .done();
+
+        let previous = world.snapshot();
+
+        world
+            .get_entity_component_mut::<LabelComponent>(&mutated)
+            .unwrap()
+            .0 = "after".to_string();
+        let added = world.create_entity().done();
+        world.delete_entity(&deleted)?;
+
+        let diff = world.diff(&previous);
+
+        assert_eq!(diff.added, [added].into_iter().collect());
+        assert_eq!(diff.removed, [deleted].into_iter().collect());
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(
+            diff.changed[&mutated],
+            vec![std::any::TypeId::of::<LabelComponent>()]
+        );
+        assert!(!diff.changed.contains_key(&unchanged));
+
+        assert_eq!(world.diff(&world.snapshot()), WorldDiff::default());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn diff_skips_types_the_snapshot_cannot_copy() -> Result<(), WorldError> {
+        let mut world = World::new();
+        world.register_cloneable::<LabelComponent>();
+        world.register_comparable::<LabelComponent>();
+        let entity_id = world
+            .create_entity()
+            .with(LabelComponent("before".to_string()))
+            .with(PositionComponent(1))
+            .done();
+
+        let previous = world.snapshot();
+
+        assert_eq!(world.diff(&previous), WorldDiff::default());
+        assert!(world.equivalent_to(&previous));
+        assert!(previous.equivalent_to(&world));
+        assert_eq!(
+            world.entity_diff(&previous, &entity_id),
+            EntityDiff::default()
+        );
+
+        world.delete_component::<PositionComponent>(&entity_id)?;
+        world
+            .get_entity_component_mut::<LabelComponent>(&entity_id)
+            .unwrap()
+            .0 = "after".to_string();
+
+        assert_eq!(
+            world.diff(&previous).changed[&entity_id],
+            vec![std::any::TypeId::of::<LabelComponent>()]
+        );
+        assert!(!world.equivalent_to(&previous));
+
+        // Worlds that aren't snapshots still compare every type
+        let mut unrelated = World::new();
+        unrelated
+            .create_entity()
+            .with(LabelComponent("after".to_string()))
+            .with(PositionComponent(1))
+            .done();
+        assert!(!world.equivalent_to(&unrelated));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn has_any_component() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        assert!(!world.has_any::<NodeDrawingComponent>());
+
+        world
+            .create_entity()
+            .with(ConnectorDrawingComponent(1))
+            .done();
+        assert!(!world.has_any::<NodeDrawingComponent>());
+
+        let entity_id = world.create_entity().with(NodeDrawingComponent(1)).done();
+        assert!(world.has_any::<NodeDrawingComponent>());
+
+        world.delete_component::<NodeDrawingComponent>(&entity_id)?;
+        assert!(!world.has_any::<NodeDrawingComponent>());
+        assert!(world.has_any::<ConnectorDrawingComponent>());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn integrate_velocities() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let moving = world
+            .create_entity()
+            .with(PositionComponent(1))
+            .with(VelocityComponent(3))
+            .done();
+        let reversing = world
+            .create_entity()
+            .with(PositionComponent(10))
+            .with(VelocityComponent(-4))
+            .done();
+        let still = world.create_entity().with(PositionComponent(7)).done();
+
+        world.integrate::<PositionComponent, VelocityComponent, _>(|position, velocity| {
+            position.0 += velocity.0;
+        });
+
+        let position = |entity_id| {
+            world
+                .get_entity_component::<PositionComponent>(&entity_id)
+                .unwrap()
+                .0
+        };
+        assert_eq!(position(moving), 4);
+        assert_eq!(position(reversing), 6);
+        assert_eq!(position(still), 7);
+        assert_eq!(
+            world
+                .get_entity_component::<VelocityComponent>(&moving)
+                .unwrap()
+                .0,
+            3
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn query_mut_with_filter() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let alive_1 = world
+            .create_entity()
+            .with(VelocityComponent(1))
+            .with(TagComponent)
+            .done();
+        let alive_2 = world
+            .create_entity()
+            .with(VelocityComponent(2))
+            .with(TagComponent)
+            .done();
+        let dead = world.create_entity().with(VelocityComponent(3)).done();
+        world.create_entity().with(TagComponent).done();
+
+        let mut visited = 0;
+        for velocity in world.query_mut_with::<VelocityComponent, TagComponent>() {
+            velocity.0 *= 10;
+            visited += 1;
+        }
+
+        assert_eq!(visited, 2);
+        let velocity = |entity_id| {
+            world
+                .get_entity_component::<VelocityComponent>(&entity_id)
+                .unwrap()
+                .0
+        };
+        assert_eq!(velocity(alive_1), 10);
+        assert_eq!(velocity(alive_2), 20);
+        assert_eq!(velocity(dead), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn done_never_returns_a_phantom_id() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let mut builder = world.create_entity();
+        builder.with(NodeDrawingComponent(1));
+        let first = builder.done();
+        let second = builder.done();
+        assert_eq!(first, second);
+
+        world.create_entity();
+        let after_dropped_builder = world.create_entity().done();
+
+        assert_eq!(world.num_entities(), 3);
+        for entity_id in [first, after_dropped_builder] {
+            assert!(world.num_components(&entity_id).is_ok());
+        }
+        assert_ne!(first, after_dropped_builder);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn extend_from_rows() -> Result<(), WorldError> {
+        let mut world = World::new();
+        world.create_entity().with(NodeDrawingComponent(0)).done();
+
+        let rows = vec![
+            (NodeDrawingComponent(1), ConnectorDrawingComponent(10)),
+            (NodeDrawingComponent(2), ConnectorDrawingComponent(20)),
+            (NodeDrawingComponent(3), ConnectorDrawingComponent(30)),
+        ];
+        let entity_ids = world.extend(rows);
+
+        assert_eq!(entity_ids, vec![1, 2, 3]);
+        let mut values: Vec<(i32, i32)> = world
+            .query2::<NodeDrawingComponent, ConnectorDrawingComponent>()
+            .map(|(node, connector)| (node.0, connector.0))
+            .collect();
+        values.sort();
+        assert_eq!(values, vec![(1, 10), (2, 20), (3, 30)]);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn get_raw_component() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let entity_id = world.create_entity().with(NodeDrawingComponent(1)).done();
+        let node_type = std::any::TypeId::of::<NodeDrawingComponent>();
+
+        let raw = world.get_raw_component(&entity_id, node_type).unwrap();
+        assert!(raw.downcast_ref::<ConnectorDrawingComponent>().is_none());
+        assert_eq!(raw.downcast_ref::<NodeDrawingComponent>().unwrap().0, 1);
+
+        let raw = world.get_raw_component_mut(&entity_id, node_type).unwrap();
+        raw.downcast_mut::<NodeDrawingComponent>().unwrap().0 = 2;
+        assert_eq!(
+            world
+                .get_entity_component::<NodeDrawingComponent>(&entity_id)
+                .unwrap()
+                .0,
+            2
+        );
+
+        assert!(world
+            .get_raw_component(
+                &entity_id,
+                std::any::TypeId::of::<ConnectorDrawingComponent>()
+            )
+            .is_none());
+        assert!(world.get_raw_component(&100, node_type).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn merge_accumulator_components() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let entity_id = world.create_entity().done();
+        let sum = |total: &mut NodeDrawingComponent, new: NodeDrawingComponent| total.0 += new.0;
+
+        world.merge_component(&entity_id, NodeDrawingComponent(5), sum)?;
+        assert_eq!(
+            world
+                .get_entity_component::<NodeDrawingComponent>(&entity_id)
+                .unwrap()
+                .0,
+            5
+        );
+
+        world.merge_component(&entity_id, NodeDrawingComponent(7), sum)?;
+        assert_eq!(
+            world
+                .get_entity_component::<NodeDrawingComponent>(&entity_id)
+                .unwrap()
+                .0,
+            12
+        );
+        assert_eq!(world.num_components(&entity_id)?, 1);
+        assert_eq!(world.count::<NodeDrawingComponent>(), 1);
+
+        assert!(matches!(
+            world.merge_component(&100, NodeDrawingComponent(1), sum),
+            Err(WorldError::EntityDoesNotExist)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn scoped_components() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let entity_ids: Vec<EntityId> = (0..3)
+            .map(|i| world.create_entity().with(NodeDrawingComponent(i)).done())
+            .collect();
+
+        let scope = world.begin_scope();
+        for entity_id in &entity_ids {
+            world.add_scoped_component(scope, entity_id, ConnectorDrawingComponent(1))?;
+        }
+        world.add_component(&entity_ids[0], PortComponent(1))?;
+
+        assert_eq!(world.count::<ConnectorDrawingComponent>(), 3);
+
+        assert_eq!(world.end_scope(scope), 3);
+
+        assert_eq!(world.count::<ConnectorDrawingComponent>(), 0);
+        assert_eq!(world.count::<NodeDrawingComponent>(), 3);
+        assert!(world.has_component::<PortComponent>(&entity_ids[0]));
+        assert_eq!(world.end_scope(scope), 0);
+        assert!(matches!(
+            world.add_scoped_component(scope, &entity_ids[0], ConnectorDrawingComponent(1)),
+            Err(WorldError::ScopeDoesNotExist(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn component_histogram() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        for i in 0..6 {
+            let entity_id = world.create_entity().with(NodeDrawingComponent(i)).done();
+            if i % 2 == 0 {
+                world.add_component(&entity_id, ConnectorDrawingComponent(i))?;
+            }
+        }
+        world.create_entity().with(PortComponent(1)).done();
+        world.create_entity().done();
+
+        let histogram = world.component_histogram();
+
+        assert_eq!(histogram.len(), 3);
+        assert_eq!(
+            histogram[&std::any::TypeId::of::<NodeDrawingComponent>()],
+            6
+        );
+        assert_eq!(
+            histogram[&std::any::TypeId::of::<ConnectorDrawingComponent>()],
+            3
+        );
+        assert_eq!(histogram[&std::any::TypeId::of::<PortComponent>()], 1);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn count_without_component() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        assert_eq!(world.count_without::<NodeDrawingComponent>(), 0);
+
+        for i in 0..7 {
+            let entity_id = world.create_entity().done();
+            if i % 3 == 0 {
+                world.add_component(&entity_id, NodeDrawingComponent(i))?;
+            }
+        }
+
+        assert_eq!(world.count_without::<NodeDrawingComponent>(), 4);
+        assert_eq!(
+            world.count::<NodeDrawingComponent>() + world.count_without::<NodeDrawingComponent>(),
+            world.num_entities()
+        );
+        assert_eq!(world.count_without::<ConnectorDrawingComponent>(), 7);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn set_component() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let entity_id = world.create_entity().done();
+
+        world.set_component(&entity_id, NodeDrawingComponent(1))?;
+        world.set_component(&entity_id, NodeDrawingComponent(2))?;
+
+        assert_eq!(
+            world
+                .get_entity_component::<NodeDrawingComponent>(&entity_id)
+                .unwrap()
+                .0,
+            2
+        );
+        assert_eq!(world.count::<NodeDrawingComponent>(), 1);
+        assert!(matches!(
+            world.set_component(&100, NodeDrawingComponent(1)),
+            Err(WorldError::EntityDoesNotExist)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn replace_component_returns_old_value() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let entity_id = world.create_entity().done();
+
+        let first = world.replace_component(&entity_id, NodeDrawingComponent(1))?;
+        assert!(first.is_none());
+
+        let second = world.replace_component(&entity_id, NodeDrawingComponent(2))?;
+        assert_eq!(second.unwrap().0, 1);
+
+        assert_eq!(
+            world
+                .get_entity_component::<NodeDrawingComponent>(&entity_id)
+                .unwrap()
+                .0,
+            2
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn query_sorted_by_key() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        for value in [5, -3, 9, 0, 2, 7] {
+            world
+                .create_entity()
+                .with(NodeDrawingComponent(value))
+                .done();
+        }
+        world
+            .create_entity()
+            .with(ConnectorDrawingComponent(1))
+            .done();
+
+        let sorted: Vec<i32> = world
+            .query_sorted_by::<NodeDrawingComponent, _, _>(|node| node.0)
+            .iter()
+            .map(|node| node.0)
+            .collect();
+        assert_eq!(sorted, vec![-3, 0, 2, 5, 7, 9]);
+
+        let by_distance: Vec<i32> = world
+            .query_sorted_by::<NodeDrawingComponent, _, _>(|node| (node.0 - 4).abs())
+            .iter()
+            .map(|node| node.0)
+            .collect();
+        assert_eq!(&by_distance[..2], &[5, 2]);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn query_copied_components() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        for i in 1..=5 {
+            world.create_entity().with(WeightComponent(i)).done();
+        }
+        world.create_entity().with(NodeDrawingComponent(100)).done();
+
+        let copied_sum: i32 = world
+            .query_copied::<WeightComponent>()
+            .map(|weight| weight.0)
+            .sum();
+        let referenced_sum: i32 = world
+            .query::<WeightComponent>()
+            .map(|weight| weight.0)
+            .sum();
+
+        assert_eq!(copied_sum, 15);
+        assert_eq!(copied_sum, referenced_sum);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn despawn_batch_of_ids() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        for i in 0..5 {
+            world.create_entity().with(NodeDrawingComponent(i)).done();
+        }
+        world.delete_entity(&3)?;
+
+        let removed = world.despawn_batch(vec![0, 2, 3, 2, 50]);
+
+        assert_eq!(removed, 2);
+        assert_eq!(world.num_entities(), 2);
+        assert_eq!(world.entity_ids(), vec![1, 4]);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn poll_added_components() -> Result<(), WorldError> {
+        let mut world = World::new();
+        world.track_added::<NodeDrawingComponent>();
+
+        let entity_id_1 = world.create_entity().with(NodeDrawingComponent(1)).done();
+        let entity_id_2 = world.create_entity().done();
+        world.add_component(&entity_id_2, NodeDrawingComponent(2))?;
+        let entity_id_3 = world.create_entity().done();
+
+        assert_eq!(
+            world.query_added::<NodeDrawingComponent>(),
+            vec![entity_id_1, entity_id_2]
+        );
+
+        world.add_component(&entity_id_3, NodeDrawingComponent(3))?;
+        world.set_component(&entity_id_1, NodeDrawingComponent(10))?;
+
+        assert_eq!(
+            world.query_added::<NodeDrawingComponent>(),
+            vec![entity_id_3]
+        );
+        assert!(world.query_added::<NodeDrawingComponent>().is_empty());
+
+        world.add_component(&entity_id_1, ConnectorDrawingComponent(1))?;
+        assert!(world.query_added::<ConnectorDrawingComponent>().is_empty());
+        world.add_component(&entity_id_2, ConnectorDrawingComponent(2))?;
+        world.add_component(&entity_id_3, ConnectorDrawingComponent(3))?;
+        world.delete_component::<ConnectorDrawingComponent>(&entity_id_2)?;
+        assert_eq!(
+            world.query_added::<ConnectorDrawingComponent>(),
+            vec![entity_id_3]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn map_component_scales_every_component() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let entity_id_1 = world.create_entity().with(PositionComponent(1)).done();
+        let entity_id_2 = world.create_entity().with(PositionComponent(-4)).done();
+        world.create_entity().with(VelocityComponent(7)).done();
+
+        let touched = world.map_component::<PositionComponent, _>(|position| position.0 *= 3);
+
+        assert_eq!(touched, 2);
+        assert_eq!(
+            world.expect_component::<PositionComponent>(&entity_id_1)?.0,
+            3
+        );
+        assert_eq!(
+            world.expect_component::<PositionComponent>(&entity_id_2)?.0,
+            -12
+        );
+        assert_eq!(world.map_component::<TagComponent, _>(|_| {}), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn group_entities_by_archetype() -> Result<(), WorldError> {
+        let mut world = World::new();
+
+        let moving_1 = world
+            .create_entity()
+            .with(PositionComponent(0))
+            .with(VelocityComponent(1))
+            .done();
+        let moving_2 = world
+            .create_entity()
+            .with(VelocityComponent(2))
+            .with(PositionComponent(0))
+            .done();
+        let still = world.create_entity().with(PositionComponent(5)).done();
+        let empty = world.create_entity().done();
+
+        assert_eq!(world.archetype(&moving_1), world.archetype(&moving_2));
+        assert_ne!(world.archetype(&moving_1), world.archetype(&still));
+        assert_eq!(world.archetype(&empty), Some(vec![]));
+        assert_eq!(world.archetype(&(empty + 100)), None);
+
+        let archetypes = world.archetypes();
+        assert_eq!(archetypes.len(), 3);
+        assert_eq!(
+            archetypes[&world.archetype(&moving_1).unwrap()],
+            vec![moving_1, moving_2]
+        );
+        assert_eq!(archetypes[&world.archetype(&still).unwrap()], vec![still]);
+        assert_eq!(archetypes[&vec![]], vec![empty]);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn components_keep_insertion_order() -> Result<(), WorldError> {
+        use std::any::TypeId;
+
+        let mut world = World::new();
+        let entity_id = world
+            .create_entity()
+            .with(VelocityComponent(1))
+            .with(PortComponent(2))
+            .done();
+        world.add_component(&entity_id, PositionComponent(3))?;
+
+        assert_eq!(
+            world.ordered_component_types(&entity_id),
+            Some(vec![
+                TypeId::of::<VelocityComponent>(),
+                TypeId::of::<PortComponent>(),
+                TypeId::of::<PositionComponent>(),
+            ])
+        );
+
+        world.set_component(&entity_id, VelocityComponent(10))?;
+        world.delete_component::<PortComponent>(&entity_id)?;
+        world.add_component(&entity_id, PortComponent(20))?;
+
+        assert_eq!(
+            world.ordered_component_types(&entity_id),
+            Some(vec![
+                TypeId::of::<VelocityComponent>(),
+                TypeId::of::<PositionComponent>(),
+                TypeId::of::<PortComponent>(),
+            ])
+        );
+
+        world.delete_entity(&entity_id)?;
+        assert_eq!(world.ordered_component_types(&entity_id), None);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn compare_equivalent_worlds() -> Result<(), WorldError> {
+        let build = || {
+            let mut world = World::new();
+            world.register_comparable::<LabelComponent>();
+            world
+                .create_entity()
+                .with(LabelComponent("a".to_string()))
+                .with(TagComponent)
+                .done();
+            world
+                .create_entity()
+                .with(LabelComponent("b".to_string()))
+                .done();
+            world
+        };
+
+        let mut world_1 = build();
+        let world_2 = build();
+        assert!(world_1.equivalent_to(&world_2));
+        assert!(world_2.equivalent_to(&world_1));
+
+        world_1
+            .get_entity_component_mut::<LabelComponent>(&1)
+            .unwrap()
+            .0 = "c".to_string();
+        assert!(!world_1.equivalent_to(&world_2));
+
+        world_1.set_component(&1, LabelComponent("b".to_string()))?;
+        assert!(world_1.equivalent_to(&world_2));
+
+        world_1.delete_component::<TagComponent>(&0)?;
+        assert!(!world_1.equivalent_to(&world_2));
+
+        world_1.add_component(&0, TagComponent)?;
+        world_1.create_entity().done();
+        assert!(!world_1.equivalent_to(&world_2));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn try_get_component_distinguishes_deleted_entities() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let with_position = world.create_entity().with(PositionComponent(4)).done();
+        let without_position = world.create_entity().done();
+        let deleted = world.create_entity().with(PositionComponent(1)).done();
+        world.delete_entity(&deleted)?;
+
+        assert_eq!(
+            world
+                .try_get_component::<PositionComponent>(&with_position)?
+                .map(|position| position.0),
+            Some(4)
+        );
+        assert!(world
+            .try_get_component::<PositionComponent>(&without_position)?
+            .is_none());
+        assert!(matches!(
+            world.try_get_component::<PositionComponent>(&deleted),
+            Err(WorldError::EntityDoesNotExist)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn reserve_entity_and_populate_later() -> Result<(), WorldError> {
+        fn add_position(world: &mut World, entity_id: &EntityId) -> Result<(), WorldError> {
+            world.add_component(entity_id, PositionComponent(1))
+        }
+        fn add_velocity(world: &mut World, entity_id: &EntityId) -> Result<(), WorldError> {
+            world.add_component(entity_id, VelocityComponent(2))
+        }
+
+        let mut world = World::new();
+        let other = world.create_entity().done();
+        let entity_id = world.reserve_entity();
+
+        assert_ne!(entity_id, other);
+        assert_eq!(world.num_components(&entity_id)?, 0);
+
+        add_position(&mut world, &entity_id)?;
+        add_velocity(&mut world, &entity_id)?;
+
+        assert_eq!(
+            world.expect_component::<PositionComponent>(&entity_id)?.0,
+            1
+        );
+        assert_eq!(
+            world.expect_component::<VelocityComponent>(&entity_id)?.0,
+            2
+        );
+        assert_eq!(world.num_components(&other)?, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn pooled_components_are_reused() -> Result<(), WorldError> {
+        // This only checks correctness. Counting the saved allocations needs
+        // a bench with a counting global allocator.
+        #[derive(Default)]
+        struct BufferComponent(Vec<u8>);
+
+        let mut world = World::new();
+        world.set_pool_capacity::<BufferComponent>(1);
+
+        let entity_id_1 = world
+            .create_entity()
+            .with(BufferComponent(vec![1, 2]))
+            .done();
+        let entity_id_2 = world.create_entity().with(BufferComponent(vec![3])).done();
+        world.delete_component::<BufferComponent>(&entity_id_1)?;
+        world.delete_component::<BufferComponent>(&entity_id_2)?;
+        assert_eq!(world.pool_len::<BufferComponent>(), 1);
+
+        world.add_component(&entity_id_1, BufferComponent(vec![4]))?;
+        assert_eq!(world.pool_len::<BufferComponent>(), 0);
+        assert_eq!(
+            world.expect_component::<BufferComponent>(&entity_id_1)?.0,
+            vec![4]
+        );
+
+        world.create_entity().with(BufferComponent(vec![5])).done();
+        assert_eq!(world.count::<BufferComponent>(), 2);
+
+        world.delete_component::<BufferComponent>(&entity_id_1)?;
+        world.set_pool_capacity::<BufferComponent>(0);
+        assert_eq!(world.pool_len::<BufferComponent>(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn pool_serves_every_removal_path() -> Result<(), WorldError> {
+        #[derive(Default)]
+        struct BufferComponent(Vec<u8>);
+
+        let mut world = World::new();
+        world.set_pool_capacity::<BufferComponent>(8);
+
+        let despawned = world.create_entity().with(BufferComponent(vec![1])).done();
+        world.delete_entity(&despawned)?;
+        assert_eq!(world.pool_len::<BufferComponent>(), 1);
+
+        let entity_id = world.create_entity().done();
+        let scope = world.begin_scope();
+        world.add_scoped_component(scope, &entity_id, BufferComponent(vec![2]))?;
+        assert_eq!(world.pool_len::<BufferComponent>(), 0);
+        world.end_scope(scope);
+        assert_eq!(world.pool_len::<BufferComponent>(), 1);
+
+        world.set_component(&entity_id, BufferComponent(vec![3]))?;
+        assert_eq!(world.pool_len::<BufferComponent>(), 0);
+        let old = world.replace_component(&entity_id, BufferComponent(vec![4]))?;
+        assert_eq!(old.unwrap().0, vec![3]);
+        assert_eq!(world.pool_len::<BufferComponent>(), 0);
+        assert_eq!(
+            world.expect_component::<BufferComponent>(&entity_id)?.0,
+            vec![4]
+        );
+
+        world.clear();
+        assert_eq!(world.pool_len::<BufferComponent>(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn split_world_by_parity() -> Result<(), WorldError> {
+        let mut world = World::new();
+        for i in 0..6 {
+            world.create_entity().with(PositionComponent(i)).done();
+        }
+        world.set_parent(2, 0)?;
+        world.set_parent(3, 0)?;
+        world.set_parent(5, 1)?;
+
+        let odd = world.split(|entity_id, _components| entity_id % 2 == 1);
+
+        assert_eq!(world.entity_ids(), vec![0, 2, 4]);
+        assert_eq!(odd.entity_ids(), vec![1, 3, 5]);
+        for entity_id in odd.entity_ids() {
+            assert_eq!(
+                odd.expect_component::<PositionComponent>(&entity_id)?.0,
+                entity_id as i32
+            );
+        }
+        assert_eq!(world.count::<PositionComponent>(), 3);
+        assert_eq!(odd.count::<PositionComponent>(), 3);
+
+        assert_eq!(world.children(&0), vec![2]);
+        assert_eq!(odd.parent(&3), None);
+        assert_eq!(odd.children(&1), vec![5]);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn list_component_types_in_use() -> Result<(), WorldError> {
+        use std::any::TypeId;
+        use std::collections::HashSet;
+
+        let mut world = World::new();
+        world
+            .create_entity()
+            .with(PositionComponent(0))
+            .with(VelocityComponent(1))
+            .done();
+        world.create_entity().with(PositionComponent(2)).done();
+        let entity_id = world.create_entity().with(TagComponent).done();
+        world.create_entity().done();
+
+        let expected: HashSet<TypeId> = [
+            TypeId::of::<PositionComponent>(),
+            TypeId::of::<VelocityComponent>(),
+            TypeId::of::<TagComponent>(),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(world.all_component_types(), expected);
+
+        world.delete_entity(&entity_id)?;
+        assert!(!world
+            .all_component_types()
+            .contains(&TypeId::of::<TagComponent>()));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn export_hierarchy_to_dot() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let root = world.create_entity().done();
+        let child_1 = world.create_entity().done();
+        let child_2 = world.create_entity().done();
+        let grandchild = world.create_entity().done();
+        world.set_parent(child_1, root)?;
+        world.set_parent(child_2, root)?;
+        world.set_parent(grandchild, child_1)?;
+
+        let dot = world.to_dot();
+        let lines: Vec<&str> = dot.lines().collect();
+
+        assert_eq!(lines.first(), Some(&"digraph world {"));
+        assert_eq!(lines.last(), Some(&"}"));
+        for entity_id in [root, child_1, child_2, grandchild] {
+            assert!(lines.contains(&format!("    {entity_id};").as_str()));
+        }
+        assert!(lines.contains(&"    0 -> 1;"));
+        assert!(lines.contains(&"    0 -> 2;"));
+        assert!(lines.contains(&"    1 -> 3;"));
+        assert_eq!(lines.iter().filter(|line| line.contains("->")).count(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn build_entity_with_default_components() -> Result<(), WorldError> {
+        #[derive(Default)]
+        struct ConfigComponent {
+            retries: u32,
+            verbose: bool,
+        }
+        #[derive(Default)]
+        struct SelectedComponent;
+
+        let mut world = World::new();
+        let entity_id = world
+            .create_entity()
+            .with(PositionComponent(1))
+            .with_default::<ConfigComponent>()
+            .with_default::<SelectedComponent>()
+            .done();
+
+        let config = world.expect_component::<ConfigComponent>(&entity_id)?;
+        assert_eq!(config.retries, 0);
+        assert!(!config.verbose);
+        assert!(world.has_component::<SelectedComponent>(&entity_id));
+        assert_eq!(world.num_components(&entity_id)?, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn record_change_log() -> Result<(), WorldError> {
+        use std::any::TypeId;
+
+        let mut world = World::new();
+        world.create_entity().with(PositionComponent(0)).done();
+        assert!(world.change_log().is_empty());
+
+        world.enable_change_log();
+        let entity_id = world.create_entity().with(PositionComponent(1)).done();
+        world.add_component(&entity_id, VelocityComponent(2))?;
+        world.delete_component::<PositionComponent>(&entity_id)?;
+        world.delete_entity(&entity_id)?;
+
+        assert_eq!(
+            world.change_log(),
+            &[
+                WorldOp::Spawn(entity_id),
+                WorldOp::Add(entity_id, TypeId::of::<PositionComponent>()),
+                WorldOp::Add(entity_id, TypeId::of::<VelocityComponent>()),
+                WorldOp::Remove(entity_id, TypeId::of::<PositionComponent>()),
+                WorldOp::Despawn(entity_id),
+            ]
+        );
+
+        world.clear_change_log();
+        assert!(world.change_log().is_empty());
+        world.create_entity().done();
+        assert_eq!(world.change_log().len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn entry_component_creates_on_first_access() -> Result<(), WorldError> {
+        #[derive(Default)]
+        struct ScoreComponent(u32);
+
+        let mut world = World::new();
+        let entity_id = world.create_entity().done();
+
+        let score = world.entry_component::<ScoreComponent>(&entity_id)?;
+        assert_eq!(score.0, 0);
+        score.0 = 7;
+
+        assert_eq!(world.entry_component::<ScoreComponent>(&entity_id)?.0, 7);
+        assert_eq!(world.count::<ScoreComponent>(), 1);
+
+        world.delete_entity(&entity_id)?;
+        assert!(matches!(
+            world.entry_component::<ScoreComponent>(&entity_id),
+            Err(WorldError::EntityDoesNotExist)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn query_with_siblings() -> Result<(), WorldError> {
+        use std::any::TypeId;
+
+        let mut world = World::new();
+        world
+            .create_entity()
+            .with(PositionComponent(1))
+            .with(VelocityComponent(2))
+            .with(TagComponent)
+            .done();
+        world.create_entity().with(VelocityComponent(3)).done();
+
+        let matches: Vec<_> = world.query_with_siblings::<PositionComponent>().collect();
+        assert_eq!(matches.len(), 1);
+
+        let (position, siblings) = matches[0];
+        assert_eq!(position.0, 1);
+        assert_eq!(siblings.len(), 3);
+        assert!(siblings.contains_key(&TypeId::of::<TagComponent>()));
+        assert_eq!(
+            siblings[&TypeId::of::<VelocityComponent>()]
+                .downcast_ref::<VelocityComponent>()
+                .unwrap()
+                .0,
+            2
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn mutate_components_through_raw_hashes() -> Result<(), WorldError> {
+        use std::any::TypeId;
+
+        let mut world = World::new();
+        let entity_id_1 = world.create_entity().with(PositionComponent(1)).done();
+        let entity_id_2 = world
+            .create_entity()
+            .with(PositionComponent(2))
+            .with(VelocityComponent(5))
+            .done();
+
+        for (entity_id, components) in world.entities_mut() {
+            for component in components.values_mut() {
+                if let Some(position) = component.downcast_mut::<PositionComponent>() {
+                    position.0 += *entity_id as i32 * 10;
+                }
+            }
+            if let Some(velocity) = components.get_mut(&TypeId::of::<VelocityComponent>()) {
+                velocity.downcast_mut::<VelocityComponent>().unwrap().0 = 0;
+            }
+        }
+
+        assert_eq!(
+            world.expect_component::<PositionComponent>(&entity_id_1)?.0,
+            1
+        );
+        assert_eq!(
+            world.expect_component::<PositionComponent>(&entity_id_2)?.0,
+            12
+        );
+        assert_eq!(
+            world.expect_component::<VelocityComponent>(&entity_id_2)?.0,
+            0
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn query_in_id_range() -> Result<(), WorldError> {
+        let mut world = World::new();
+        for i in 0..10 {
+            let entity_id = world.create_entity().done();
+            if i % 3 != 0 {
+                world.add_component(&entity_id, PositionComponent(i))?;
+            }
+        }
+
+        let slice: Vec<(EntityId, i32)> = world
+            .query_in_range::<PositionComponent>(2..8)
+            .map(|(entity_id, position)| (entity_id, position.0))
+            .collect();
+        assert_eq!(slice, vec![(2, 2), (4, 4), (5, 5), (7, 7)]);
+
+        assert_eq!(world.query_in_range::<PositionComponent>(0..10).count(), 6);
+        assert_eq!(world.query_in_range::<PositionComponent>(10..20).count(), 0);
+        assert_eq!(
+            world
+                .query_in_range::<PositionComponent>(0..EntityId::MAX)
+                .count(),
+            6
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn component_count_or_zero() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let entity_id = world
+            .create_entity()
+            .with(PositionComponent(0))
+            .with(VelocityComponent(1))
+            .done();
+        let empty_id = world.create_entity().done();
+
+        assert_eq!(world.component_count_or_zero(&entity_id), 2);
+        assert_eq!(world.component_count_or_zero(&empty_id), 0);
+        assert_eq!(world.component_count_or_zero(&1000), 0);
+
+        world.delete_entity(&entity_id)?;
+        assert_eq!(world.component_count_or_zero(&entity_id), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "deterministic")]
+    pub fn deterministic_iteration_order() -> Result<(), WorldError> {
+        let mut world = World::with_allocator(enco::allocator::RecyclingAllocator::default());
+        for i in 0..20 {
+            world.create_entity().with(PositionComponent(i)).done();
+        }
+        for entity_id in [3, 11, 7] {
+            world.delete_entity(&entity_id)?;
+        }
+        world.create_entity().with(PositionComponent(100)).done();
+
+        let ids: Vec<EntityId> = world.iter().copied().collect();
+        assert_eq!(ids, world.entity_ids());
+
+        let positions: Vec<i32> = world.query::<PositionComponent>().map(|p| p.0).collect();
+        let expected: Vec<i32> = (0..20)
+            .map(|i| if i == 7 { 100 } else { i })
+            .filter(|i| *i != 3 && *i != 11)
+            .collect();
+        assert_eq!(positions, expected);
+
+        let positions: Vec<i32> = world
+            .query_mut::<PositionComponent>()
+            .map(|p| p.0)
+            .collect();
+        assert_eq!(positions, expected);
+
+        for entity_id in world.entity_ids() {
+            world.add_component(&entity_id, VelocityComponent(entity_id as i32))?;
+            world.add_component(&entity_id, TagComponent)?;
+        }
+        let joined: Vec<EntityId> = world
+            .join::<PositionComponent, VelocityComponent>()
+            .map(|(entity_id, _position, _velocity)| entity_id)
+            .collect();
+        assert_eq!(joined, world.entity_ids());
+        let positions: Vec<i32> = world
+            .query2::<PositionComponent, VelocityComponent>()
+            .map(|(p, _v)| p.0)
+            .collect();
+        assert_eq!(positions, expected);
+        let velocities: Vec<i32> = world
+            .query3::<PositionComponent, VelocityComponent, TagComponent>()
+            .map(|(_p, v, _t)| v.0)
+            .collect();
+        assert_eq!(
+            velocities,
+            world
+                .entity_ids()
+                .into_iter()
+                .map(|entity_id| entity_id as i32)
+                .collect::<Vec<_>>()
+        );
+        let drained: Vec<EntityId> = world
+            .drain::<TagComponent>()
+            .into_iter()
+            .map(|(entity_id, _tag)| entity_id)
+            .collect();
+        assert_eq!(drained, world.entity_ids());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn reindex_compacts_ids() -> Result<(), WorldError> {
+        let mut world = World::new();
+        for i in 0..6 {
+            world.create_entity().with(PositionComponent(i)).done();
+        }
+        world.set_parent(5, 2)?;
+        world.set_parent(4, 5)?;
+        for entity_id in [1, 3] {
+            world.delete_entity(&entity_id)?;
+        }
+
+        let remap = world.reindex();
+
+        assert_eq!(remap.len(), 4);
+        assert_eq!(remap[&0], 0);
+        assert_eq!(remap[&2], 1);
+        assert_eq!(remap[&4], 2);
+        assert_eq!(remap[&5], 3);
+        assert_eq!(world.entity_ids(), vec![0, 1, 2, 3]);
+
+        for (old_id, new_id) in &remap {
+            assert_eq!(
+                world.expect_component::<PositionComponent>(new_id)?.0,
+                *old_id as i32
+            );
+        }
+        assert_eq!(world.children(&1), vec![3]);
+        assert_eq!(world.parent(&2), Some(3));
+        assert_eq!(world.count::<PositionComponent>(), 4);
+        assert!(world.has_component::<PositionComponent>(&3));
+
+        assert_eq!(world.create_entity().done(), 4);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn query_mutable_and_shared_components() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let moving = world
+            .create_entity()
+            .with(PositionComponent(1))
+            .with(VelocityComponent(3))
+            .done();
+        let still = world.create_entity().with(PositionComponent(7)).done();
+
+        for _ in 0..2 {
+            for (position, velocity) in
+                world.query_mut_read::<PositionComponent, VelocityComponent>()
+            {
+                position.0 += velocity.0;
+            }
+        }
+
+        assert_eq!(world.expect_component::<PositionComponent>(&moving)?.0, 7);
+        assert_eq!(world.expect_component::<VelocityComponent>(&moving)?.0, 3);
+        assert_eq!(world.expect_component::<PositionComponent>(&still)?.0, 7);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn estimate_component_memory() -> Result<(), WorldError> {
+        struct TransformComponent([f64; 4]);
+
+        let mut world = World::new();
+        world.register_sized_component::<PositionComponent>();
+        world.register_sized_component::<TransformComponent>();
+        assert_eq!(world.memory_estimate(), 0);
+
+        world
+            .create_entity()
+            .with(PositionComponent(1))
+            .with(TransformComponent([0.0; 4]))
+            .with(VelocityComponent(1))
+            .done();
+        world.create_entity().with(PositionComponent(2)).done();
+        let entity_id = world.create_entity().with(PositionComponent(3)).done();
+
+        assert_eq!(
+            world.query::<TransformComponent>().next().unwrap().0,
+            [0.0; 4]
+        );
+        assert_eq!(world.memory_estimate(), 3 * 4 + 32);
+
+        world.delete_entity(&entity_id)?;
+        assert_eq!(world.memory_estimate(), 2 * 4 + 32);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn take_and_reinsert_entity() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let entity_id = world
+            .create_entity()
+            .with(PositionComponent(4))
+            .with(LabelComponent("crate".to_string()))
+            .done();
+        world.create_entity().with(PositionComponent(0)).done();
+
+        let components = world.take_entity(&entity_id)?;
+        assert_eq!(components.len(), 2);
+        assert_eq!(world.num_entities(), 1);
+        assert_eq!(world.count::<PositionComponent>(), 1);
+        assert!(matches!(
+            world.take_entity(&entity_id),
+            Err(WorldError::EntityDoesNotExist)
+        ));
+
+        let new_id = world.insert_entity(components);
+        assert_eq!(world.num_entities(), 2);
+        assert_eq!(world.count::<PositionComponent>(), 2);
+        assert_eq!(world.expect_component::<PositionComponent>(&new_id)?.0, 4);
+        assert_eq!(
+            world.expect_component::<LabelComponent>(&new_id)?.0,
+            "crate"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn fetch_two_components_of_an_entity() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let both = world
+            .create_entity()
+            .with(PositionComponent(1))
+            .with(VelocityComponent(2))
+            .done();
+        let position_only = world.create_entity().with(PositionComponent(3)).done();
+
+        let (position, velocity) = world
+            .components_of::<PositionComponent, VelocityComponent>(&both)
+            .unwrap();
+        assert_eq!((position.0, velocity.0), (1, 2));
+
+        assert!(world
+            .components_of::<PositionComponent, VelocityComponent>(&position_only)
+            .is_none());
+        assert!(world
+            .components_of::<PositionComponent, VelocityComponent>(&1000)
+            .is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn paginate_query() -> Result<(), WorldError> {
+        let mut world = World::new();
+        for i in 0..150 {
+            let builder = &mut world.create_entity();
+            if i % 3 != 2 {
+                builder.with(PositionComponent(i));
+            }
+        }
+        assert_eq!(world.count::<PositionComponent>(), 100);
+
+        let ids_of = |page: Vec<(EntityId, &PositionComponent)>| -> Vec<EntityId> {
+            page.into_iter()
+                .map(|(entity_id, position)| {
+                    assert_eq!(position.0, entity_id as i32);
+                    entity_id
+                })
+                .collect()
+        };
+
+        let first = ids_of(world.query_page::<PositionComponent>(0, 40));
+        let second = ids_of(world.query_page::<PositionComponent>(40, 40));
+        let last = ids_of(world.query_page::<PositionComponent>(80, 40));
+
+        assert_eq!(first.len(), 40);
+        assert_eq!(second.len(), 40);
+        assert_eq!(last.len(), 20);
+        assert_eq!(&first[..3], &[0, 1, 3]);
+        assert!(first.last() < second.first());
+        assert!(second.last() < last.first());
+        assert_eq!(last.last(), Some(&148));
+        assert_eq!(ids_of(world.query_page::<PositionComponent>(0, 40)), first);
+        assert!(world.query_page::<PositionComponent>(100, 10).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn clone_component_between_entities() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let from = world
+            .create_entity()
+            .with(LabelComponent("door".to_string()))
+            .done();
+        let to = world.create_entity().done();
+        let empty = world.create_entity().done();
+
+        world.clone_component::<LabelComponent>(&from, &to)?;
+        assert_eq!(world.expect_component::<LabelComponent>(&from)?.0, "door");
+        assert_eq!(world.expect_component::<LabelComponent>(&to)?.0, "door");
+        assert_eq!(world.count::<LabelComponent>(), 2);
+
+        assert!(matches!(
+            world.clone_component::<LabelComponent>(&from, &to),
+            Err(WorldError::EntityAlreadyHasComponent(_))
+        ));
+        assert!(matches!(
+            world.clone_component::<LabelComponent>(&empty, &to),
+            Err(WorldError::EntityDoesNotHaveComponent(_))
+        ));
+        assert!(matches!(
+            world.clone_component::<LabelComponent>(&1000, &empty),
+            Err(WorldError::EntityDoesNotExist)
+        ));
+        assert!(matches!(
+            world.clone_component::<LabelComponent>(&from, &1000),
+            Err(WorldError::EntityDoesNotExist)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "already has")]
+    pub fn strict_builder_panics_on_duplicate_component() {
+        let mut world = World::new();
+        assert_eq!(world.strict_mode(), StrictMode::Strict);
+
+        world
+            .create_entity()
+            .with(PositionComponent(1))
+            .with(PositionComponent(2))
+            .done();
+    }
+
+    #[test]
+    pub fn lenient_builder_reports_duplicate_component() -> Result<(), WorldError> {
+        let mut world = World::new();
+        world.set_strict_mode(StrictMode::Lenient);
+
+        let result = world
+            .create_entity()
+            .with(PositionComponent(1))
+            .with(PositionComponent(2))
+            .with(VelocityComponent(3))
+            .try_done();
+        assert!(matches!(
+            result,
+            Err(WorldError::EntityAlreadyHasComponent(_))
+        ));
+
+        let entity_id = world.entity_ids()[0];
+        assert_eq!(
+            world.expect_component::<PositionComponent>(&entity_id)?.0,
+            1
+        );
+        assert_eq!(
+            world.expect_component::<VelocityComponent>(&entity_id)?.0,
+            3
+        );
+
+        let entity_id = world
+            .create_entity()
+            .with(PositionComponent(4))
+            .try_done()?;
+        assert_eq!(
+            world.expect_component::<PositionComponent>(&entity_id)?.0,
+            4
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn query_cache_is_invalidated_by_structural_changes() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let entity_id_1 = world.create_entity().with(PositionComponent(1)).done();
+        let entity_id_2 = world.create_entity().with(VelocityComponent(2)).done();
+
+        let positions = |world: &World| -> Vec<i32> {
+            world.query::<PositionComponent>().map(|p| p.0).collect()
+        };
+        assert_eq!(positions(&world), vec![1]);
+
+        world.add_component(&entity_id_2, PositionComponent(2))?;
+        assert_eq!(positions(&world), vec![1, 2]);
+
+        world
+            .get_entity_component_mut::<PositionComponent>(&entity_id_1)
+            .unwrap()
+            .0 = 10;
+        assert_eq!(positions(&world), vec![10, 2]);
+
+        world.delete_component::<PositionComponent>(&entity_id_1)?;
+        assert_eq!(positions(&world), vec![2]);
+
+        world.create_entity().with(PositionComponent(3)).done();
+        assert_eq!(positions(&world), vec![2, 3]);
+
+        world.delete_entity(&entity_id_2)?;
+        assert_eq!(positions(&world), vec![3]);
+        assert_eq!(world.query::<VelocityComponent>().count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn chain_resource_insertions() -> Result<(), WorldError> {
+        struct Gravity(i32);
+        struct Title(String);
+
+        let mut world = World::new();
+        world
+            .insert_resource(Gravity(10))
+            .insert_resource(Title("level 1".to_string()))
+            .create_entity()
+            .with(PositionComponent(0))
+            .done();
+
+        assert_eq!(world.resource::<Gravity>().unwrap().0, 10);
+        assert_eq!(world.resource::<Title>().unwrap().0, "level 1");
+
+        world.resource_mut::<Gravity>().unwrap().0 = 5;
+        world.insert_resource(Title("level 2".to_string()));
+        assert_eq!(world.resource::<Gravity>().unwrap().0, 5);
+        assert_eq!(world.resource::<Title>().unwrap().0, "level 2");
+
+        assert_eq!(world.remove_resource::<Gravity>().unwrap().0, 5);
+        assert!(world.resource::<Gravity>().is_none());
+        assert!(world.remove_resource::<Gravity>().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn dense_slots_follow_sorted_ids() -> Result<(), WorldError> {
+        let mut world = World::new();
+        for i in 0..10 {
+            let entity_id = world.create_entity().done();
+            if i % 2 == 0 {
+                world.add_component(&entity_id, PositionComponent(i))?;
+            }
+        }
+        world.delete_entity(&4)?;
+
+        let (entity_ids, slots) = world.query_dense::<PositionComponent>();
+        assert_eq!(entity_ids, vec![0, 2, 6, 8]);
+        assert_eq!(slots.len(), entity_ids.len());
+        for (slot, entity_id) in entity_ids.iter().enumerate() {
+            assert_eq!(slots[entity_id], slot);
+        }
+
+        let (entity_ids, slots) = world.query_dense::<VelocityComponent>();
+        assert!(entity_ids.is_empty());
+        assert!(slots.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn truncate_drops_high_ids() -> Result<(), WorldError> {
+        let mut world = World::new();
+        for i in 0..10 {
+            world.create_entity().with(PositionComponent(i)).done();
+        }
+        world.set_parent(7, 2)?;
+
+        assert_eq!(world.truncate(5), 5);
+        assert_eq!(world.entity_ids(), vec![0, 1, 2, 3, 4]);
+        assert_eq!(world.count::<PositionComponent>(), 5);
+        assert!(world.children(&2).is_empty());
+
+        assert_eq!(world.create_entity().done(), 5);
+        assert_eq!(world.truncate(100), 0);
+        assert_eq!(world.create_entity().done(), 6);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn integrate_components_with_a_resource() -> Result<(), WorldError> {
+        struct Speed(i32);
+
+        let mut world = World::new();
+        world.insert_resource(Speed(3));
+        let entity_id_1 = world.create_entity().with(PositionComponent(0)).done();
+        let entity_id_2 = world.create_entity().with(PositionComponent(10)).done();
+        world.create_entity().with(VelocityComponent(1)).done();
+
+        for _ in 0..2 {
+            let (speed, positions) = world
+                .resource_and_query_mut::<Speed, PositionComponent>()
+                .unwrap();
+            for position in positions {
+                position.0 += speed.0;
+            }
+        }
+
+        assert_eq!(
+            world.expect_component::<PositionComponent>(&entity_id_1)?.0,
+            6
+        );
+        assert_eq!(
+            world.expect_component::<PositionComponent>(&entity_id_2)?.0,
+            16
+        );
+        assert!(world
+            .resource_and_query_mut::<String, PositionComponent>()
+            .is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn shrink_one_component_type() -> Result<(), WorldError> {
+        let mut world = World::new();
+        for i in 0..50 {
+            world
+                .create_entity()
+                .with(PositionComponent(i))
+                .with(VelocityComponent(-i))
+                .done();
+        }
+        for entity_id in 0..45 {
+            world.delete_component::<PositionComponent>(&entity_id)?;
+        }
+
+        world.shrink_component_type::<PositionComponent>();
+
+        assert_eq!(world.count::<PositionComponent>(), 5);
+        for entity_id in 45..50 {
+            assert_eq!(
+                world.expect_component::<PositionComponent>(&entity_id)?.0,
+                entity_id as i32
+            );
+        }
+        assert_eq!(world.count::<VelocityComponent>(), 50);
+
+        world.create_entity().with(PositionComponent(100)).done();
+        assert_eq!(world.query::<PositionComponent>().count(), 6);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn collect_and_write_back_a_column() -> Result<(), WorldError> {
+        let mut world = World::new();
+        for i in 0..6 {
+            let entity_id = world.create_entity().done();
+            if i != 3 {
+                world.add_component(&entity_id, WeightComponent(i))?;
+            }
+        }
+
+        let (entity_ids, mut weights) = world.collect_column::<WeightComponent>();
+        assert_eq!(entity_ids, vec![0, 1, 2, 4, 5]);
+        assert_eq!(
+            weights.iter().map(|weight| weight.0).collect::<Vec<_>>(),
+            vec![0, 1, 2, 4, 5]
+        );
+
+        for weight in &mut weights {
+            weight.0 *= 10;
+        }
+        for (entity_id, weight) in entity_ids.iter().zip(weights) {
+            world.set_component(entity_id, weight)?;
+        }
+
+        assert_eq!(world.expect_component::<WeightComponent>(&4)?.0, 40);
+        assert_eq!(world.expect_component::<WeightComponent>(&5)?.0, 50);
+        assert!(!world.has_component::<WeightComponent>(&3));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn add_component_and_mutate_it() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let entity_id = world.create_entity().done();
+
+        world.add_component_mut(&entity_id, PositionComponent(1))?.0 += 41;
+        assert_eq!(
+            world.expect_component::<PositionComponent>(&entity_id)?.0,
+            42
+        );
+
+        assert!(matches!(
+            world.add_component_mut(&entity_id, PositionComponent(0)),
+            Err(WorldError::EntityAlreadyHasComponent(_))
+        ));
+        assert_eq!(
+            world.expect_component::<PositionComponent>(&entity_id)?.0,
+            42
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn tag_entities() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let goblin = world.create_entity().done();
+        let orc = world.create_entity().done();
+        let coin = world.create_entity().done();
+
+        world.add_tag(goblin, "enemy")?;
+        world.add_tag(orc, "enemy")?;
+        world.add_tag(orc, "boss")?;
+        world.add_tag(coin, "pickup")?;
+        world.add_tag(coin, "pickup")?;
+
+        assert!(world.has_tag(orc, "boss"));
+        assert!(!world.has_tag(goblin, "boss"));
+        assert!(!world.has_tag(goblin, "unknown"));
+        assert_eq!(world.entities_with_tag("enemy"), vec![goblin, orc]);
+        assert_eq!(world.entities_with_tag("pickup"), vec![coin]);
+        assert!(world.entities_with_tag("unknown").is_empty());
+        assert!(matches!(
+            world.add_tag(1000, "enemy"),
+            Err(WorldError::EntityDoesNotExist)
+        ));
+
+        assert!(world.remove_tag(goblin, "enemy"));
+        assert!(!world.remove_tag(goblin, "enemy"));
+        assert_eq!(world.entities_with_tag("enemy"), vec![orc]);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn deleting_an_entity_drops_its_tags() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let entity_id = world.create_entity().done();
+        world.add_tag(entity_id, "enemy")?;
+        world.add_tag(entity_id, "flying")?;
+
+        world.delete_entity(&entity_id)?;
+
+        assert!(world.entities_with_tag("enemy").is_empty());
+        assert!(world.entities_with_tag("flying").is_empty());
+        assert!(!world.has_tag(entity_id, "enemy"));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn fold_component_matches_query() -> Result<(), WorldError> {
+        let mut world = World::new();
+        for i in 0..10 {
+            let mut builder = world.create_entity();
+            builder.with(WeightComponent(i));
+            if i % 3 == 0 {
+                builder.with(TagComponent);
+            }
+            builder.done();
+        }
+
+        let mut total = 0;
+        for weight in world.query::<WeightComponent>() {
+            total += weight.0;
+        }
+
+        assert_eq!(
+            world.fold_component(0, |sum, weight: &WeightComponent| sum + weight.0),
+            total
+        );
+        assert_eq!(
+            world.fold_component(0, |count, _tag: &TagComponent| count + 1),
+            4
+        );
+        assert_eq!(
+            world.fold_component(7, |sum, port: &PortComponent| sum + port.0),
+            7
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn structural_generation() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let mut generation = world.structural_generation();
+        let mut bumped = |world: &World| {
+            let changed = world.structural_generation() > generation;
+            generation = world.structural_generation();
+            changed
+        };
+
+        let entity_id = world.create_entity().done();
+        assert!(bumped(&world));
+        world.add_component(&entity_id, PositionComponent(1))?;
+        assert!(bumped(&world));
+
+        assert_eq!(world.query::<PositionComponent>().count(), 1);
+        world
+            .get_entity_component_mut::<PositionComponent>(&entity_id)
+            .unwrap()
+            .0 += 1;
+        world.set_component(&entity_id, PositionComponent(3))?;
+        world.map_component(|position: &mut PositionComponent| position.0 += 1);
+        assert!(!bumped(&world));
+
+        world.delete_component::<PositionComponent>(&entity_id)?;
+        assert!(bumped(&world));
+        world.delete_entity(&entity_id)?;
+        assert!(bumped(&world));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn incomplete_is_complement_of_join() -> Result<(), WorldError> {
+        use std::collections::HashSet;
+
+        let mut world = World::new();
+        for i in 0..12 {
+            let mut builder = world.create_entity();
+            if i % 2 == 0 {
+                builder.with(PositionComponent(i));
+            }
+            if i % 3 == 0 {
+                builder.with(VelocityComponent(i));
+            }
+            builder.done();
+        }
+
+        let equipped: HashSet<EntityId> = world
+            .join::<PositionComponent, VelocityComponent>()
+            .map(|(entity_id, _position, _velocity)| entity_id)
+            .collect();
+        let expected: HashSet<EntityId> = world
+            .entity_ids()
+            .into_iter()
+            .filter(|entity_id| !equipped.contains(entity_id))
+            .collect();
+        let incomplete: HashSet<EntityId> = world
+            .incomplete::<PositionComponent, VelocityComponent>()
+            .collect();
+
+        assert_eq!(incomplete, expected);
+        assert_eq!(incomplete.len(), 10);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn transfer_all_components() -> Result<(), WorldError> {
+        let mut world = World::new();
+        for i in 0..5 {
+            world.create_entity().with(PositionComponent(i)).done();
+        }
+        world.create_entity().with(TagComponent).done();
+
+        let transferred =
+            world.transfer_all(|position: PositionComponent| VelocityComponent(position.0 * 2));
+
+        assert_eq!(transferred, 5);
+        assert_eq!(world.query::<PositionComponent>().count(), 0);
+        assert_eq!(world.query::<VelocityComponent>().count(), 5);
+        assert_eq!(
+            world
+                .get_entity_component::<VelocityComponent>(&3)
+                .unwrap()
+                .0,
+            6
+        );
+        assert_eq!(
+            world.transfer_all(|position: PositionComponent| position),
+            0
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn sample_eligible_entity() -> Result<(), WorldError> {
+        // A seeded linear congruential generator stands in for a real RNG
+        let mut state: u64 = 42;
+        let mut next_index = |n: usize| {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 33) as usize % n
+        };
+
+        let mut world = World::new();
+        let mut eligible = vec![];
+        for i in 0..10 {
+            let mut builder = world.create_entity();
+            if i % 3 == 0 {
+                builder.with(PositionComponent(i));
+            }
+            let entity_id = builder.done();
+            if i % 3 == 0 {
+                eligible.push(entity_id);
+            }
+        }
+
+        for _ in 0..20 {
+            let (entity_id, position) = world
+                .sample_with::<PositionComponent>(&mut next_index)
+                .unwrap();
+            assert!(eligible.contains(&entity_id));
+            assert_eq!(position.0 as EntityId, entity_id);
+        }
+        assert!(world
+            .sample_with::<VelocityComponent>(&mut next_index)
+            .is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    pub fn sample_with_rng() -> Result<(), WorldError> {
+        use rand::SeedableRng;
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+        let mut world = World::new();
+        for i in 0..10 {
+            let mut builder = world.create_entity();
+            if i % 3 == 0 {
+                builder.with(PositionComponent(i));
+            }
+            builder.done();
+        }
+
+        let mut seen = std::collections::HashSet::new();
+        for _ in 0..100 {
+            let (entity_id, position) = world.sample::<PositionComponent>(&mut rng).unwrap();
+            assert_eq!(entity_id % 3, 0);
+            assert_eq!(position.0 as EntityId, entity_id);
+            seen.insert(entity_id);
+        }
+        assert_eq!(seen.len(), 4);
+        assert!(world.sample::<VelocityComponent>(&mut rng).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "out of 1 candidates")]
+    pub fn sample_with_out_of_range_pick_panics() {
+        let mut world = World::new();
+        world.create_entity().with(PositionComponent(0)).done();
+        world.sample_with::<PositionComponent>(|n| n);
+    }
+
+    #[test]
+    pub fn entities_with_runtime_types() -> Result<(), WorldError> {
+        use std::any::TypeId;
+
+        let mut world = World::new();
+        for i in 0..12 {
+            let mut builder = world.create_entity();
+            if i % 2 == 0 {
+                builder.with(PositionComponent(i));
+            }
+            if i % 3 == 0 {
+                builder.with(VelocityComponent(i));
+            }
+            builder.done();
+        }
+
+        let mut expected: Vec<EntityId> = world
+            .join::<PositionComponent, VelocityComponent>()
+            .map(|(entity_id, _position, _velocity)| entity_id)
+            .collect();
+        expected.sort();
+        let required = [
+            TypeId::of::<PositionComponent>(),
+            TypeId::of::<VelocityComponent>(),
+        ];
+
+        assert_eq!(world.entities_with(&required), expected);
+        assert_eq!(
+            expected.len(),
+            world
+                .query2::<PositionComponent, VelocityComponent>()
+                .count()
+        );
+        assert!(world
+            .entities_with(&[TypeId::of::<TagComponent>()])
+            .is_empty());
+        assert_eq!(world.entities_with(&[]).len(), 12);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn spawn_n_entities() -> Result<(), WorldError> {
+        let mut world = World::new();
+        world.create_entity().done();
+
+        let ids: [EntityId; 4] = world.spawn_n();
+        assert_eq!(ids.len(), 4);
+        assert_eq!(ids, [1, 2, 3, 4]);
+
+        let [a, b, c] = world.spawn_n();
+        assert!(a != b && b != c && a != c);
+        assert_eq!(world.num_entities(), 8);
+        assert_eq!(world.spawn_n::<0>(), []);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn interned_components_share_storage() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let mut entity_ids = vec![];
+        for _ in 0..10 {
+            let entity_id = world.create_entity().done();
+            world.add_interned(&entity_id, MaterialComponent("steel".to_string()))?;
+            entity_ids.push(entity_id);
+        }
+        let other = world.create_entity().done();
+        world.add_interned(&other, MaterialComponent("wood".to_string()))?;
+
+        for entity_id in &entity_ids {
+            assert_eq!(
+                world.get_interned::<MaterialComponent>(entity_id),
+                Some(&MaterialComponent("steel".to_string()))
+            );
+        }
+        assert_eq!(world.interned_count::<MaterialComponent>(), 2);
+        let first = world
+            .get_entity_component::<Interned<MaterialComponent>>(&entity_ids[0])
+            .unwrap();
+        let last = world
+            .get_entity_component::<Interned<MaterialComponent>>(&entity_ids[9])
+            .unwrap();
+        assert!(Interned::ptr_eq(first, last));
+
+        assert!(matches!(
+            world.add_interned(&other, MaterialComponent("glass".to_string())),
+            Err(WorldError::EntityAlreadyHasComponent(_))
+        ));
+        assert!(world
+            .add_interned(&100, MaterialComponent("glass".to_string()))
+            .is_err());
+        assert_eq!(world.interned_count::<MaterialComponent>(), 2);
+        assert!(!world.has_component::<MaterialComponent>(&other));
+        assert_eq!(world.count::<Interned<MaterialComponent>>(), 11);
+
+        world.delete_entity(&other)?;
+        world.shrink_to_fit();
+        assert_eq!(world.interned_count::<MaterialComponent>(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn on_empty_fires_once_per_transition() -> Result<(), WorldError> {
+        let fired = Rc::new(RefCell::new(0));
+        let counter = Rc::clone(&fired);
+
+        let mut world = World::new();
+        world.set_on_empty(move || *counter.borrow_mut() += 1);
+        let a = world.create_entity().with(PositionComponent(1)).done();
+        let b = world.create_entity().done();
+
+        world.delete_entity(&a)?;
+        assert_eq!(*fired.borrow(), 0);
+        world.delete_entity(&b)?;
+        assert_eq!(*fired.borrow(), 1);
+
+        world.clear();
+        assert!(world.delete_entity(&b).is_err());
+        assert_eq!(*fired.borrow(), 1);
+
+        for _ in 0..3 {
+            world.create_entity().done();
+        }
+        world.clear();
+        assert_eq!(*fired.borrow(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn for_each_with_world_lookups() -> Result<(), WorldError> {
+        let mut world = World::new();
+        for i in 0..6 {
+            let mut builder = world.create_entity();
+            builder.with(PositionComponent(i));
+            if i % 2 == 0 {
+                builder.with(VelocityComponent(i));
+            }
+            builder.done();
+        }
+
+        let mut visited = vec![];
+        let mut with_velocity = vec![];
+        world.for_each_with_world(|world, entity_id, position: &PositionComponent| {
+            visited.push(position.0);
+            if world.has_component::<VelocityComponent>(&entity_id) {
+                with_velocity.push(entity_id);
+            }
+            assert_eq!(world.count::<PositionComponent>(), 6);
+        });
+
+        assert_eq!(visited, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(with_velocity, vec![0, 2, 4]);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn presence_mask_matches_has_component() -> Result<(), WorldError> {
+        let mut world = World::new();
+        for i in 0..10 {
+            let mut builder = world.create_entity();
+            if i % 2 == 1 {
+                builder.with(TagComponent);
+            }
+            builder.done();
+        }
+        world.delete_entity(&4)?;
+
+        let (entity_ids, mask) = world.presence_mask::<TagComponent>();
+
+        assert_eq!(entity_ids, world.entity_ids());
+        assert_eq!(mask.len(), entity_ids.len());
+        for (entity_id, present) in entity_ids.iter().zip(&mask) {
+            assert_eq!(*present, world.has_component::<TagComponent>(entity_id));
+        }
+        assert_eq!(mask.iter().filter(|present| **present).count(), 5);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn merge_resources_policies() -> Result<(), WorldError> {
+        for overwrite in [false, true] {
+            let mut world = World::new();
+            world.insert_resource(PositionComponent(1));
+            world.insert_resource(TagComponent);
+            let mut other = World::new();
+            other.insert_resource(PositionComponent(2));
+            other.insert_resource(VelocityComponent(3));
+
+            world.merge_resources(&mut other, overwrite);
+
+            let (kept, left) = if overwrite { (2, None) } else { (1, Some(2)) };
+            assert_eq!(world.resource::<PositionComponent>().unwrap().0, kept);
+            assert_eq!(world.resource::<VelocityComponent>().unwrap().0, 3);
+            assert!(world.resource::<TagComponent>().is_some());
+            assert_eq!(
+                other
+                    .resource::<PositionComponent>()
+                    .map(|position| position.0),
+                left
+            );
+            assert!(other.resource::<VelocityComponent>().is_none());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn try_for_each_mut_stops_at_first_error() -> Result<(), WorldError> {
+        let mut world = World::new();
+        for i in 0..3 {
+            world.create_entity().with(PositionComponent(i)).done();
+        }
+
+        let mut visited = 0;
+        let result = world.try_for_each_mut(|position: &mut PositionComponent| {
+            visited += 1;
+            if visited == 2 {
+                return Err(position.0);
+            }
+            position.0 += 10;
+            Ok(())
+        });
+
+        assert!(result.is_err());
+        assert_eq!(visited, 2);
+        let untouched = world
+            .query::<PositionComponent>()
+            .filter(|position| position.0 < 10)
+            .count();
+        assert_eq!(untouched, 2);
+        assert_eq!(
+            world.try_for_each_mut(|_position: &mut PositionComponent| Ok::<(), ()>(())),
+            Ok(())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn assert_component_passes() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let entity_id = world
+            .create_entity()
+            .with(LabelComponent("node".to_string()))
+            .done();
+
+        world.assert_component(&entity_id, &LabelComponent("node".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "expected LabelComponent(\"edge\")")]
+    pub fn assert_component_panics_on_mismatch() {
+        let mut world = World::new();
+        let entity_id = world
+            .create_entity()
+            .with(LabelComponent("node".to_string()))
+            .done();
+
+        world.assert_component(&entity_id, &LabelComponent("edge".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "has no")]
+    pub fn assert_component_panics_when_missing() {
+        let mut world = World::new();
+        let entity_id = world.create_entity().done();
+
+        world.assert_component(&entity_id, &LabelComponent("node".to_string()));
+    }
+
+    #[test]
+    pub fn active_borrows_follow_iterators() -> Result<(), WorldError> {
+        let mut world = World::new();
+        world
+            .create_entity()
+            .with(PositionComponent(0))
+            .with(VelocityComponent(1))
+            .done();
+        assert_eq!(world.active_borrows(), 0);
+
+        let positions = world.query::<PositionComponent>();
+        assert_eq!(world.active_borrows(), 1);
+        let pairs = world.join::<PositionComponent, VelocityComponent>();
+        assert_eq!(world.active_borrows(), 2);
+        let (_count, velocities) = world.query_counted::<VelocityComponent>();
+        assert_eq!(world.active_borrows(), 3);
+        drop(positions);
+        drop(pairs);
+        drop(velocities);
+        assert_eq!(world.active_borrows(), 0);
+
+        let total: i32 = world
+            .query2::<PositionComponent, VelocityComponent>()
+            .map(|(position, velocity)| position.0 + velocity.0)
+            .sum();
+        assert_eq!(total, 1);
+        assert_eq!(world.active_borrows(), 0);
+        world.create_entity().done();
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn reduce_components_into_resource() -> Result<(), WorldError> {
+        let mut world = World::new();
+        for i in 1..=4 {
+            world.create_entity().with(WeightComponent(i)).done();
+        }
+        world.create_entity().with(TagComponent).done();
+
+        assert!(matches!(
+            world.reduce_into_resource(
+                |total: &mut PositionComponent, weight: &WeightComponent| { total.0 += weight.0 }
+            ),
+            Err(WorldError::ResourceDoesNotExist(_))
+        ));
+
+        world.insert_resource(PositionComponent(100));
+        world.reduce_into_resource(|total: &mut PositionComponent, weight: &WeightComponent| {
+            total.0 += weight.0
+        })?;
+
+        assert_eq!(world.resource::<PositionComponent>().unwrap().0, 110);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn despawn_all_with_tag() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let mut survivors = vec![];
+        for i in 0..6 {
+            let entity_id = world.create_entity().with(PositionComponent(i)).done();
+            if i % 2 == 0 {
+                world.add_tag(entity_id, "projectile")?;
+            } else {
+                world.add_tag(entity_id, "enemy")?;
+                survivors.push(entity_id);
+            }
+        }
+
+        assert_eq!(world.despawn_all_with_tag("projectile"), 3);
+
+        assert_eq!(world.entity_ids(), survivors);
+        assert!(world.entities_with_tag("projectile").is_empty());
+        assert_eq!(world.entities_with_tag("enemy"), survivors);
+        assert_eq!(world.count::<PositionComponent>(), 3);
+        assert!(world.validate().is_empty());
+        assert_eq!(world.despawn_all_with_tag("projectile"), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn builder_with_if() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let mut entity_ids = vec![];
+        for flag in [true, false] {
+            entity_ids.push(
+                world
+                    .create_entity()
+                    .with(PositionComponent(1))
+                    .with_if(flag, TagComponent)
+                    .done(),
+            );
+        }
+
+        assert!(world.has_component::<TagComponent>(&entity_ids[0]));
+        assert!(!world.has_component::<TagComponent>(&entity_ids[1]));
+        assert_eq!(world.count::<PositionComponent>(), 2);
+        assert_eq!(world.num_components(&entity_ids[1])?, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn single_with_no_match() -> Result<(), WorldError> {
+        let mut world = World::new();
+        world.create_entity().with(VelocityComponent(0)).done();
+
+        assert!(matches!(
+            world.single::<PositionComponent>(),
+            Err(WorldError::NoMatchingEntity(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn single_with_one_match() -> Result<(), WorldError> {
+        let mut world = World::new();
+        world.create_entity().with(VelocityComponent(0)).done();
+        let entity_id = world.create_entity().with(PositionComponent(7)).done();
+
+        let (single_id, position) = world.single::<PositionComponent>()?;
+        assert_eq!((single_id, position.0), (entity_id, 7));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn single_with_many_matches() -> Result<(), WorldError> {
+        let mut world = World::new();
+        world.create_entity().with(PositionComponent(1)).done();
+        world.create_entity().with(PositionComponent(2)).done();
+
+        assert!(matches!(
+            world.single::<PositionComponent>(),
+            Err(WorldError::MultipleMatchingEntities(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn set_component_with_teardown() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let entity_id = world.create_entity().done();
+        let mut replaced = vec![];
+
+        world.set_component_with(&entity_id, PositionComponent(1), |old| replaced.push(old.0))?;
+        assert!(replaced.is_empty());
+
+        world.set_component_with(&entity_id, PositionComponent(2), |old| replaced.push(old.0))?;
+        assert_eq!(replaced, vec![1]);
+        assert_eq!(
+            world
+                .get_entity_component::<PositionComponent>(&entity_id)
+                .unwrap()
+                .0,
+            2
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn query_counted_matches_yielded() -> Result<(), WorldError> {
+        let mut world = World::new();
+        for i in 0..7 {
+            let mut builder = world.create_entity();
+            if i % 2 == 0 {
+                builder.with(PositionComponent(i));
+            }
+            builder.done();
+        }
+        world.delete_entity(&2)?;
+
+        let (count, positions) = world.query_counted::<PositionComponent>();
+        let mut values = Vec::with_capacity(count);
+        values.extend(positions.map(|position| position.0));
+
+        assert_eq!(count, 3);
+        assert_eq!(values.len(), count);
+        assert_eq!(world.query_counted::<TagComponent>().0, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn component_entry_or_insert_on_absent() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let entity_id = world.create_entity().done();
+
+        let entry = world.component_entry::<PositionComponent>(&entity_id)?;
+        assert!(!entry.is_occupied());
+        entry.or_insert(PositionComponent(1)).0 += 1;
+
+        assert_eq!(
+            world
+                .get_entity_component::<PositionComponent>(&entity_id)
+                .unwrap()
+                .0,
+            2
+        );
+        assert!(matches!(
+            world.component_entry::<PositionComponent>(&42),
+            Err(WorldError::EntityDoesNotExist)
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn component_entry_and_modify_on_present() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let entity_id = world.create_entity().with(PositionComponent(5)).done();
+
+        world
+            .component_entry::<PositionComponent>(&entity_id)?
+            .and_modify(|position| position.0 *= 2);
+        let position = world
+            .component_entry::<PositionComponent>(&entity_id)?
+            .or_insert_with(|| panic!("the component is already there"));
+
+        assert_eq!(position.0, 10);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn component_entry_chained() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let entity_id = world.create_entity().done();
+
+        for _ in 0..3 {
+            world
+                .component_entry::<PositionComponent>(&entity_id)?
+                .and_modify(|position| position.0 += 1)
+                .or_insert(PositionComponent(0));
+        }
+
+        assert_eq!(
+            world
+                .get_entity_component::<PositionComponent>(&entity_id)
+                .unwrap()
+                .0,
+            2
+        );
+        assert_eq!(world.num_components(&entity_id)?, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn query_across_worlds() -> Result<(), WorldError> {
+        let mut first = World::new();
+        let mut second = World::new();
+        first.create_entity().with(PositionComponent(10)).done();
+        first.create_entity().done();
+        first.create_entity().with(PositionComponent(11)).done();
+        second.create_entity().with(PositionComponent(20)).done();
+
+        let results: Vec<(usize, EntityId, i32)> =
+            query_all::<PositionComponent>(&[&first, &second])
+                .map(|(index, entity_id, position)| (index, entity_id, position.0))
+                .collect();
+
+        assert_eq!(results, vec![(0, 0, 10), (0, 2, 11), (1, 0, 20)]);
+        assert_eq!(query_all::<PositionComponent>(&[]).count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn prune_empty_entities() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let stripped = world
+            .create_entity()
+            .with(PositionComponent(1))
+            .with(TagComponent)
+            .done();
+        let kept = world.create_entity().with(PositionComponent(2)).done();
+        let reserved = world.reserve_entity();
+        let filled = world.reserve_entity();
+        world.add_component(&filled, TagComponent)?;
+        world.delete_component::<TagComponent>(&filled)?;
+
+        world.delete_component::<PositionComponent>(&stripped)?;
+        assert_eq!(world.prune_empty(), 1);
+        assert!(world.num_components(&filled).is_err());
+        world.delete_component::<TagComponent>(&stripped)?;
+
+        assert_eq!(world.prune_empty(), 1);
+        assert_eq!(world.entity_ids(), vec![kept, reserved]);
+        assert_eq!(
+            world
+                .get_entity_component::<PositionComponent>(&kept)
+                .unwrap()
+                .0,
+            2
+        );
+        assert_eq!(world.prune_empty(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn split_keeps_reserved_entities() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let reserved = world.reserve_entity();
+        let empty = world.create_entity().with(TagComponent).done();
+        world.delete_component::<TagComponent>(&empty)?;
+        world.create_entity().with(PositionComponent(1)).done();
+
+        let mut split = world.split(|_entity_id, components| components.is_empty());
+
+        assert_eq!(split.entity_ids(), vec![reserved, empty]);
+        assert_eq!(split.prune_empty(), 1);
+        assert_eq!(split.entity_ids(), vec![reserved]);
+        assert_eq!(world.prune_empty(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn entity_diff_lists_changed_type() -> Result<(), WorldError> {
+        use std::any::TypeId;
+
+        let mut world = World::new();
+        world.register_cloneable::<LabelComponent>();
+        world.register_comparable::<LabelComponent>();
+        world.register_cloneable::<WeightComponent>();
+        let entity_id = world
+            .create_entity()
+            .with(LabelComponent("before".to_string()))
+            .with(WeightComponent(1))
+            .done();
+        let other = world
+            .create_entity()
+            .with(LabelComponent("other".to_string()))
+            .done();
+
+        let previous = world.snapshot();
+        world
+            .get_entity_component_mut::<LabelComponent>(&entity_id)
+            .unwrap()
+            .0 = "after".to_string();
+
+        let diff = world.entity_diff(&previous, &entity_id);
+        assert_eq!(diff.changed, vec![TypeId::of::<LabelComponent>()]);
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+        assert_eq!(world.entity_diff(&previous, &other), EntityDiff::default());
+
+        world.delete_entity(&other)?;
+        let spawned = world.create_entity().with(WeightComponent(2)).done();
+        assert_eq!(
+            world.entity_diff(&previous, &other).removed,
+            vec![TypeId::of::<LabelComponent>()]
+        );
+        assert_eq!(
+            world.entity_diff(&previous, &spawned).added,
+            vec![TypeId::of::<WeightComponent>()]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn batch_structural_coalesces_notifications() -> Result<(), WorldError> {
+        use std::any::TypeId;
+
+        let hook_calls = Rc::new(RefCell::new(0));
+        let batches = Rc::new(RefCell::new(vec![]));
+        let mut world = World::new();
+        {
+            let hook_calls = Rc::clone(&hook_calls);
+            world.register_on_add::<PositionComponent>(move |_| *hook_calls.borrow_mut() += 1);
+            let batches = Rc::clone(&batches);
+            world.set_on_batch(move |type_ids| batches.borrow_mut().push(type_ids.to_vec()));
+        }
+        let removed = world.create_entity().with(TagComponent).done();
+        assert_eq!(*hook_calls.borrow(), 0);
+
+        world.batch_structural(|world| {
+            for i in 0..50 {
+                world
+                    .create_entity()
+                    .with(PositionComponent(i))
+                    .with(VelocityComponent(i))
+                    .done();
+            }
+            world.batch_structural(|world| {
+                world.delete_component::<TagComponent>(&removed).unwrap();
+            });
+        });
+
+        assert_eq!(*hook_calls.borrow(), 0);
+        let mut expected = vec![
+            TypeId::of::<PositionComponent>(),
+            TypeId::of::<VelocityComponent>(),
+            TypeId::of::<TagComponent>(),
+        ];
+        expected.sort();
+        assert_eq!(*batches.borrow(), vec![expected]);
+
+        world.create_entity().with(PositionComponent(0)).done();
+        assert_eq!(*hook_calls.borrow(), 1);
+        assert_eq!(batches.borrow().len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn batch_structural_ends_on_panic() -> Result<(), WorldError> {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let hook_calls = Rc::new(RefCell::new(0));
+        let mut world = World::new();
+        {
+            let hook_calls = Rc::clone(&hook_calls);
+            world.register_on_add::<PositionComponent>(move |_| *hook_calls.borrow_mut() += 1);
+        }
+
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            world.batch_structural(|world| {
+                world.batch_structural(|world| {
+                    world.create_entity().with(PositionComponent(0)).done();
+                    panic!("failed halfway through the batch");
+                });
+            });
+        }));
+        assert!(result.is_err());
+        assert_eq!(*hook_calls.borrow(), 0);
+
+        world.create_entity().with(PositionComponent(1)).done();
+        assert_eq!(*hook_calls.borrow(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn two_entities_component_mut() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let a = world.create_entity().with(PositionComponent(1)).done();
+        let b = world.create_entity().with(PositionComponent(9)).done();
+
+        let (position_b, position_a) = world
+            .get_two_entities_component_mut::<PositionComponent>(&b, &a)
+            .unwrap();
+        std::mem::swap(&mut position_a.0, &mut position_b.0);
+
+        assert_eq!(
+            world
+                .get_entity_component::<PositionComponent>(&a)
+                .unwrap()
+                .0,
+            9
+        );
+        assert_eq!(
+            world
+                .get_entity_component::<PositionComponent>(&b)
+                .unwrap()
+                .0,
+            1
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn two_entities_component_mut_rejects_same_entity() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let a = world.create_entity().with(PositionComponent(1)).done();
+
+        assert!(world
+            .get_two_entities_component_mut::<PositionComponent>(&a, &a)
+            .is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn two_entities_component_mut_missing_component() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let a = world.create_entity().with(PositionComponent(1)).done();
+        let b = world.create_entity().with(VelocityComponent(2)).done();
+
+        assert!(world
+            .get_two_entities_component_mut::<PositionComponent>(&a, &b)
+            .is_none());
+        assert!(world
+            .get_two_entities_component_mut::<PositionComponent>(&a, &1000)
+            .is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn drain_all_entities() -> Result<(), WorldError> {
+        use std::any::TypeId;
+
+        let mut world = World::new();
+        world
+            .create_entity()
+            .with(PositionComponent(1))
+            .with(VelocityComponent(2))
+            .done();
+        world.create_entity().with(PositionComponent(3)).done();
+        world.create_entity().done();
+
+        let entities = world.drain_entities();
+
+        let ids: Vec<EntityId> = entities.iter().map(|(entity_id, _)| *entity_id).collect();
+        assert_eq!(ids, vec![0, 1, 2]);
+        let sizes: Vec<usize> = entities
+            .iter()
+            .map(|(_entity_id, components)| components.len())
+            .collect();
+        assert_eq!(sizes, vec![2, 1, 0]);
+        let position = entities[1].1[&TypeId::of::<PositionComponent>()]
+            .downcast_ref::<PositionComponent>()
+            .unwrap();
+        assert_eq!(position.0, 3);
+
+        assert!(world.is_empty());
+        assert_eq!(world.count::<PositionComponent>(), 0);
+        assert_eq!(world.create_entity().done(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn query_shards_partition_query() -> Result<(), WorldError> {
+        let mut world = World::new();
+        for i in 0..20 {
+            let mut builder = world.create_entity();
+            if i % 3 != 0 {
+                builder.with(PositionComponent(i));
+            }
+            builder.done();
+        }
+
+        let num_shards = 4;
+        let mut union = vec![];
+        for shard in 0..num_shards {
+            let ids: Vec<EntityId> = world
+                .query_shard::<PositionComponent>(shard, num_shards)
+                .map(|(entity_id, position)| {
+                    assert_eq!(position.0 as EntityId, entity_id);
+                    entity_id
+                })
+                .collect();
+            assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+            assert!(ids.iter().all(|entity_id| entity_id % num_shards == shard));
+            union.extend(ids);
+        }
+        union.sort();
+
+        let mut all: Vec<EntityId> = world
+            .query::<PositionComponent>()
+            .map(|position| position.0 as EntityId)
+            .collect();
+        all.sort();
+        assert_eq!(union, all);
+        assert_eq!(world.query_shard::<PositionComponent>(0, 0).count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn broadcast_component_to_lacking_entities() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let source = world
+            .create_entity()
+            .with(LabelComponent("shared".to_string()))
+            .done();
+        let own = world
+            .create_entity()
+            .with(LabelComponent("own".to_string()))
+            .done();
+        let lacking = [
+            world.create_entity().done(),
+            world.create_entity().with(PositionComponent(1)).done(),
+            world.create_entity().done(),
+        ];
+
+        assert_eq!(world.broadcast_component::<LabelComponent>(&source)?, 3);
+
+        for entity_id in &lacking {
+            assert_eq!(
+                world.get_entity_component::<LabelComponent>(entity_id),
+                world.get_entity_component::<LabelComponent>(&source)
+            );
+        }
+        assert_eq!(
+            world
+                .get_entity_component::<LabelComponent>(&own)
+                .unwrap()
+                .0,
+            "own"
+        );
+        assert_eq!(world.count::<LabelComponent>(), 5);
+        assert!(matches!(
+            world.broadcast_component::<WeightComponent>(&source),
+            Err(WorldError::EntityDoesNotHaveComponent(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn restore_resources_from_snapshot() -> Result<(), WorldError> {
+        let mut world = World::new();
+        world.register_cloneable::<LabelComponent>();
+        world.register_cloneable::<WeightComponent>();
+        world.insert_resource(LabelComponent("dark".to_string()));
+        world.insert_resource(PositionComponent(1));
+
+        let snapshot = world.snapshot_resources();
+        assert_eq!(snapshot.len(), 1);
+        assert!(snapshot.contains::<LabelComponent>());
+        assert!(!snapshot.contains::<PositionComponent>());
+
+        world.resource_mut::<LabelComponent>().unwrap().0 = "light".to_string();
+        world.resource_mut::<PositionComponent>().unwrap().0 = 2;
+        world.insert_resource(WeightComponent(3));
+        world.restore_resources(&snapshot);
+
+        assert_eq!(world.resource::<LabelComponent>().unwrap().0, "dark");
+        assert_eq!(world.resource::<PositionComponent>().unwrap().0, 2);
+        assert_eq!(world.resource::<WeightComponent>().unwrap().0, 3);
+
+        world.resource_mut::<LabelComponent>().unwrap().0 = "light".to_string();
+        world.restore_resources(&snapshot);
+        assert_eq!(world.resource::<LabelComponent>().unwrap().0, "dark");
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn component_size_of_present_components() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let entity_id = world
+            .create_entity()
+            .with(PositionComponent(1))
+            .with(MaterialComponent("steel".to_string()))
+            .with(TagComponent)
+            .done();
+
+        assert_eq!(
+            world.component_size::<PositionComponent>(&entity_id),
+            Some(std::mem::size_of::<PositionComponent>())
+        );
+        assert_eq!(
+            world.component_size::<MaterialComponent>(&entity_id),
+            Some(std::mem::size_of::<MaterialComponent>())
+        );
+        assert_eq!(world.component_size::<TagComponent>(&entity_id), Some(0));
+        assert_eq!(world.component_size::<VelocityComponent>(&entity_id), None);
+        assert_eq!(
+            world.component_size::<PositionComponent>(&(entity_id + 1)),
+            None
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn entities_ranked_by_component_count() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let two = world
+            .create_entity()
+            .with(PositionComponent(0))
+            .with(VelocityComponent(0))
+            .done();
+        let none = world.create_entity().done();
+        let one = world.create_entity().with(PositionComponent(1)).done();
+        let other_one = world.create_entity().with(TagComponent).done();
+
+        assert_eq!(
+            world.entities_by_component_count(false),
+            vec![(none, 0), (one, 1), (other_one, 1), (two, 2)]
+        );
+        assert_eq!(
+            world.entities_by_component_count(true),
+            vec![(two, 2), (one, 1), (other_one, 1), (none, 0)]
+        );
+        assert!(World::new().entities_by_component_count(true).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    pub fn sync_component_upserts() -> Result<(), WorldError> {
+        let mut world = World::new();
+        let a = world.create_entity().with(PositionComponent(1)).done();
+        let b = world.create_entity().done();
+        let c = world.create_entity().with(PositionComponent(3)).done();
+
+        let applied = world.sync_component(vec![
+            (a, PositionComponent(10)),
+            (b, PositionComponent(20)),
+            (a, PositionComponent(11)),
+        ])?;
+
+        assert_eq!(applied, 3);
+        let position = |world: &World, entity_id| {
+            world
+                .get_entity_component::<PositionComponent>(&entity_id)
+                .unwrap()
+                .0
+        };
+        assert_eq!(position(&world, a), 11);
+        assert_eq!(position(&world, b), 20);
+        assert_eq!(position(&world, c), 3);
+
+        assert!(matches!(
+            world.sync_component(vec![(c, PositionComponent(30)), (42, PositionComponent(0))]),
+            Err(WorldError::EntityDoesNotExist)
+        ));
+        assert_eq!(position(&world, c), 3);
+        assert_eq!(
+            world.sync_component(Vec::<(EntityId, PositionComponent)>::new())?,
+            0
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "iterator(s) over it were alive")]
+    pub fn delete_while_iterating_panics() {
+        let mut world = World::new();
+        let entity_id = world.create_entity().with(PositionComponent(1)).done();
+
+        let positions = world.query::<PositionComponent>();
+        std::mem::forget(positions);
+        let _ = world.delete_entity(&entity_id);
+    }
+
     struct NodeDrawingComponent(i32);
     struct ConnectorDrawingComponent(i32);
+    struct PortComponent(i32);
+    struct PositionComponent(i32);
+    struct VelocityComponent(i32);
+    #[derive(Debug, Clone, PartialEq)]
+    struct LabelComponent(String);
+    struct CollisionEvent(EntityId, EntityId);
+    struct TagComponent;
+    #[derive(Clone, Copy)]
+    struct WeightComponent(i32);
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct MaterialComponent(String);
 }